@@ -0,0 +1,66 @@
+use crate::{
+    bitboard::{king_attacks, knight_attacks, pawn_attacks, BitBoard},
+    board::{Board, Color, Soldier},
+    magic::{bishop_attacks, queen_attacks, rook_attacks},
+    vector::Vector,
+};
+
+impl Board {
+    // pieces of the given color attacking the square, with sliders blocked by
+    // the occupancy passed in; pieces missing from it are left out, so a
+    // capture sequence can be played out by removing pieces one at a time
+    pub fn attackers_to(&self, pos: Vector, color: Color, occupied: BitBoard) -> BitBoard {
+        let diagonal = self
+            .soldiers(Soldier::Bishop)
+            .union(&self.soldiers(Soldier::Queen));
+        let straight = self
+            .soldiers(Soldier::Rook)
+            .union(&self.soldiers(Soldier::Queen));
+        // a pawn attacks the square if an enemy pawn on the square would attack it
+        pawn_attacks(pos, color.opposite())
+            .intersection(&self.soldiers(Soldier::Pawn))
+            .union(&knight_attacks(pos).intersection(&self.soldiers(Soldier::Knight)))
+            .union(&king_attacks(pos).intersection(&self.soldiers(Soldier::King)))
+            .union(&bishop_attacks(pos, occupied).intersection(&diagonal))
+            .union(&rook_attacks(pos, occupied).intersection(&straight))
+            .intersection(&self.color_occupancy(color))
+            .intersection(&occupied)
+    }
+
+    pub fn is_attacked(&self, pos: Vector, by: Color) -> bool {
+        !self.attackers_to(pos, by, self.occupancy()).is_empty()
+    }
+
+    // every square attacked by the color, whether empty, enemy or defended
+    pub fn attack_map(&self, color: Color) -> BitBoard {
+        self.color_occupancy(color)
+            .iter()
+            .fold(BitBoard::new_empty(), |map, pos| {
+                map.union(&self.piece_attacks(pos))
+            })
+    }
+
+    // how many pieces of the color attack each square
+    pub fn attacker_counts(&self, color: Color) -> [u8; 64] {
+        let mut counts = [0; 64];
+        for pos in self.color_occupancy(color).iter() {
+            for target in self.piece_attacks(pos).iter() {
+                counts[target.as_num()] += 1;
+            }
+        }
+        counts
+    }
+
+    // squares the piece on pos attacks, ignoring pins
+    fn piece_attacks(&self, pos: Vector) -> BitBoard {
+        let (s, c) = self.get(pos).expect("no piece to attack with");
+        match s {
+            Soldier::Pawn => pawn_attacks(pos, c),
+            Soldier::Knight => knight_attacks(pos),
+            Soldier::Bishop => bishop_attacks(pos, self.occupancy()),
+            Soldier::Rook => rook_attacks(pos, self.occupancy()),
+            Soldier::Queen => queen_attacks(pos, self.occupancy()),
+            Soldier::King => king_attacks(pos),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::{
+    env,
+    io::{self, BufRead},
+    iter, process,
+};
+
+mod output;
+mod perft;
+mod test;
+mod uci;
+mod xboard;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("perft") {
+        if let Err(e) = perft::run(&args[1..]) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // the first command tells us which protocol the interface speaks
+    let mut lines = io::stdin().lock().lines().map_while(Result::ok);
+    match lines.next() {
+        Some(line) if line.trim() == "xboard" => xboard::run(lines),
+        Some(line) => uci::run(iter::once(line).chain(lines)),
+        None => {}
+    }
+}
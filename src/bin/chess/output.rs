@@ -0,0 +1,20 @@
+use std::fmt::Display;
+
+// where protocol replies go, shared with the search threads; tests collect
+// the lines instead of printing them
+#[derive(Clone)]
+pub enum Output {
+    Stdout,
+    #[cfg(test)]
+    Lines(std::sync::Arc<std::sync::Mutex<Vec<String>>>),
+}
+
+impl Output {
+    pub fn send(&self, line: impl Display) {
+        match self {
+            Output::Stdout => println!("{}", line),
+            #[cfg(test)]
+            Output::Lines(lines) => lines.lock().unwrap().push(line.to_string()),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Instant;
+
+use chess::{
+    divide, divide_parallel, legal_moves, perft_hashed, perft_stats_by_depth, PerftTable, State,
+};
+
+const USAGE: &str = "usage: perft [--stats] [--hash <mb> | --threads <n>] <depth> [fen]";
+
+// perft [--stats] [--hash <mb> | --threads <n>] <depth> [fen], printing the node
+// count of every root move, or with --stats the breakdown of move types at every depth
+pub fn run(mut args: &[String]) -> Result<(), String> {
+    let mut stats = false;
+    let mut table = None;
+    let mut threads = 1;
+    loop {
+        match args.first().map(String::as_str) {
+            Some("--stats") => {
+                stats = true;
+                args = &args[1..];
+            }
+            Some("--hash") => {
+                let mb = args.get(1).and_then(|mb| mb.parse().ok()).ok_or(USAGE)?;
+                table = Some(PerftTable::with_megabytes(mb));
+                args = &args[2..];
+            }
+            Some("--threads") => {
+                threads = args.get(1).and_then(|n| n.parse().ok()).ok_or(USAGE)?;
+                args = &args[2..];
+            }
+            _ => break,
+        }
+    }
+    if table.is_some() && threads > 1 {
+        return Err("--hash and --threads cannot be combined".to_string());
+    }
+    if table.is_some() && stats {
+        return Err("--hash and --stats cannot be combined".to_string());
+    }
+    let depth: usize = args.first().and_then(|d| d.parse().ok()).ok_or(USAGE)?;
+    let mut st = if args.len() > 1 {
+        State::try_from_fen(&args[1..].join(" ")).map_err(|e| e.to_string())?
+    } else {
+        State::new()
+    };
+
+    let start = Instant::now();
+    let total = if stats {
+        print_stats(&mut st, depth)
+    } else {
+        print_divide(&mut st, depth, table.as_mut(), threads)
+    };
+    let elapsed = start.elapsed();
+
+    println!();
+    println!("Nodes: {}", total);
+    println!("Time: {:.3}s", elapsed.as_secs_f64());
+    println!(
+        "NPS: {:.0}",
+        total as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+    Ok(())
+}
+
+fn print_divide(
+    st: &mut State,
+    depth: usize,
+    table: Option<&mut PerftTable>,
+    threads: usize,
+) -> u64 {
+    let divided = match table {
+        Some(table) if depth > 0 => legal_moves(st)
+            .into_iter()
+            .map(|mv| {
+                st.push(mv);
+                let nodes = perft_hashed(st, depth - 1, table);
+                st.pop();
+                (mv, nodes)
+            })
+            .collect(),
+        _ if threads > 1 => divide_parallel(st, depth, threads),
+        _ => divide(st, depth),
+    };
+    let mut divided: Vec<(String, u64)> = divided
+        .into_iter()
+        .map(|(mv, nodes)| (mv.to_uci(), nodes))
+        .collect();
+    divided.sort();
+
+    for (mv, nodes) in &divided {
+        println!("{}: {}", mv, nodes);
+    }
+    if depth == 0 {
+        1
+    } else {
+        divided.iter().map(|(_, n)| n).sum()
+    }
+}
+
+fn print_stats(st: &mut State, depth: usize) -> u64 {
+    println!(
+        "{:>5} {:>12} {:>10} {:>8} {:>8} {:>10} {:>9} {:>9} {:>9} {:>9}",
+        "depth",
+        "nodes",
+        "captures",
+        "e.p.",
+        "castles",
+        "promotions",
+        "checks",
+        "disc.",
+        "double",
+        "mates"
+    );
+    let by_depth = perft_stats_by_depth(st, depth);
+    for (d, s) in by_depth.iter().enumerate() {
+        println!(
+            "{:>5} {:>12} {:>10} {:>8} {:>8} {:>10} {:>9} {:>9} {:>9} {:>9}",
+            d + 1,
+            s.nodes,
+            s.captures,
+            s.en_passants,
+            s.castles,
+            s.promotions,
+            s.checks,
+            s.discovered_checks,
+            s.double_checks,
+            s.checkmates
+        );
+    }
+    // the node count is the leaves at the requested depth, not the sum of the rows
+    by_depth.last().map_or(1, |s| s.nodes)
+}
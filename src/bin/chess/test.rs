@@ -0,0 +1,217 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use chess::State;
+
+    use crate::{output::Output, uci::Uci, xboard::XBoard};
+
+    fn collected() -> (Output, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        (Output::Lines(lines.clone()), lines)
+    }
+
+    // waits until a line starting with prefix is sent, for at most the timeout
+    fn wait_for(lines: &Mutex<Vec<String>>, prefix: &str, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if lines.lock().unwrap().iter().any(|l| l.starts_with(prefix)) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    // the move in the first bestmove line
+    fn best_move(lines: &Mutex<Vec<String>>) -> String {
+        assert!(wait_for(lines, "bestmove ", Duration::from_secs(5)));
+        let lines = lines.lock().unwrap();
+        let line = lines.iter().find(|l| l.starts_with("bestmove ")).unwrap();
+        line["bestmove ".len()..].to_string()
+    }
+
+    fn assert_legal(st: &State, mv: &str) {
+        assert!(st.parse_uci(mv).is_ok(), "{} is not legal", mv);
+    }
+
+    #[test]
+    fn uci_position_test() {
+        // an illegal move never leaves the previous game's position behind
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        for cmd in [
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "position startpos moves e2e5",
+            "go movetime 100",
+        ] {
+            assert!(uci.handle(cmd));
+        }
+        assert_legal(&State::new(), &best_move(&lines));
+        assert!(lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|l| l.starts_with("info string") && l.contains("e2e5")));
+
+        // the moves before the illegal one are kept
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        uci.handle("position startpos moves e2e4 e7e5 e1e3");
+        uci.handle("go depth 2");
+        let mut st = State::new();
+        for mv in ["e2e4", "e7e5"] {
+            let mv = st.parse_uci(mv).unwrap();
+            st.push(mv);
+        }
+        assert_legal(&st, &best_move(&lines));
+
+        // so is nothing of the old position after an invalid fen
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/R5K1 x - - 0 1");
+        uci.handle("go depth 2");
+        assert_legal(&State::new(), &best_move(&lines));
+
+        // a fen with moves: black is to move afterwards
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1 moves g1f1");
+        uci.handle("go depth 2");
+        assert_legal(
+            &State::from_fen("6k1/5ppp/8/8/8/8/8/R4K2 b - - 1 1"),
+            &best_move(&lines),
+        );
+    }
+
+    #[test]
+    fn uci_go_test() {
+        // clock limits end the search on their own
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        uci.handle("position startpos moves e2e4");
+        uci.handle("go wtime 1000 btime 1000 winc 0 binc 0 movestogo 10");
+        assert_legal(
+            &State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            &best_move(&lines),
+        );
+        assert!(lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|l| l.starts_with("info depth ")));
+
+        // infinite waits for stop
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        uci.handle("position startpos");
+        uci.handle("go infinite");
+        thread::sleep(Duration::from_millis(100));
+        assert!(!lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|l| l.starts_with("bestmove ")));
+        uci.handle("stop");
+        assert_legal(&State::new(), &best_move(&lines));
+
+        // handshake and unknown commands
+        let (out, lines) = collected();
+        let mut uci = Uci::new(out);
+        for cmd in ["uci", "isready", "frobnicate"] {
+            uci.handle(cmd);
+        }
+        let lines = lines.lock().unwrap();
+        assert!(lines.iter().any(|l| l == "uciok"));
+        assert!(lines.iter().any(|l| l == "readyok"));
+        assert!(lines
+            .iter()
+            .any(|l| l == "info string unknown command frobnicate"));
+        drop(lines);
+        assert!(!uci.handle("quit"));
+    }
+
+    #[test]
+    fn xboard_time_test() {
+        // over the clock: move at once instead of searching without a limit
+        let (out, lines) = collected();
+        let mut xboard = XBoard::new(out);
+        for cmd in ["new", "time -500", "usermove e2e4"] {
+            assert!(xboard.handle(cmd));
+        }
+        assert!(wait_for(&lines, "move ", Duration::from_secs(2)));
+        xboard.handle("quit");
+
+        // unparsable times are ignored
+        let (out, lines) = collected();
+        let mut xboard = XBoard::new(out);
+        for cmd in ["new", "time 100", "time 1e3", "otim 100", "usermove e2e4"] {
+            xboard.handle(cmd);
+        }
+        assert!(wait_for(&lines, "move ", Duration::from_secs(2)));
+        xboard.handle("quit");
+
+        // the level base time is the clock until a time command arrives
+        let (out, lines) = collected();
+        let mut xboard = XBoard::new(out);
+        for cmd in ["new", "level 40 0:05 0", "usermove e2e4"] {
+            xboard.handle(cmd);
+        }
+        assert!(wait_for(&lines, "move ", Duration::from_secs(2)));
+        xboard.handle("quit");
+    }
+
+    #[test]
+    fn xboard_result_test() {
+        // a search running when the game ends never sends its move
+        let (out, lines) = collected();
+        let mut xboard = XBoard::new(out);
+        for cmd in [
+            "new",
+            "level 40 5 0",
+            "usermove e2e4",
+            "result 1-0 {White resigns}",
+        ] {
+            xboard.handle(cmd);
+        }
+        xboard.handle("ping 1");
+        assert!(wait_for(&lines, "pong 1", Duration::from_secs(2)));
+        thread::sleep(Duration::from_millis(100));
+        assert!(!lines.lock().unwrap().iter().any(|l| l.starts_with("move ")));
+
+        // nor does the engine start thinking again
+        xboard.handle("usermove d2d4");
+        thread::sleep(Duration::from_millis(100));
+        assert!(!lines.lock().unwrap().iter().any(|l| l.starts_with("move ")));
+        xboard.handle("quit");
+    }
+
+    #[test]
+    fn xboard_force_test() {
+        let (out, lines) = collected();
+        let mut xboard = XBoard::new(out);
+        for cmd in [
+            "new",
+            "force",
+            "usermove e2e4",
+            "usermove e7e5",
+            "sd 2",
+            "go",
+        ] {
+            xboard.handle(cmd);
+        }
+        assert!(wait_for(&lines, "move ", Duration::from_secs(2)));
+        xboard.handle("usermove e2e5");
+        assert!(wait_for(
+            &lines,
+            "Illegal move: e2e5",
+            Duration::from_secs(2)
+        ));
+        assert!(!xboard.handle("quit"));
+    }
+}
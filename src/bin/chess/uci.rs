@@ -0,0 +1,188 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chess::{allocate_time, search, Color, SearchInfo, SearchLimits, State};
+
+use crate::output::Output;
+
+const ENGINE_NAME: &str = "rust-chess";
+
+// a search running in the background, printing its own bestmove when done
+struct Search {
+    handle: JoinHandle<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Search {
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap();
+    }
+}
+
+pub struct Uci {
+    st: State,
+    search: Option<Search>,
+    out: Output,
+}
+
+pub fn run(lines: impl Iterator<Item = String>) {
+    let mut uci = Uci::new(Output::Stdout);
+    for line in lines {
+        if !uci.handle(&line) {
+            break;
+        }
+    }
+    uci.stop_search();
+}
+
+impl Uci {
+    pub fn new(out: Output) -> Self {
+        Uci {
+            st: State::new(),
+            search: None,
+            out,
+        }
+    }
+
+    // returns false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first().copied() {
+            Some("uci") => {
+                self.out.send(format!("id name {}", ENGINE_NAME));
+                self.out.send("id author thebenkogan");
+                self.out.send("uciok");
+            }
+            Some("isready") => self.out.send("readyok"),
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.st = State::new();
+            }
+            Some("position") => {
+                self.stop_search();
+                if let Err(e) = self.position(&tokens[1..]) {
+                    self.out.send(format!("info string {}", e));
+                }
+            }
+            Some("go") => {
+                self.stop_search();
+                self.go(&tokens[1..]);
+            }
+            Some("stop") => self.stop_search(),
+            Some("quit") => return false,
+            // there are no options yet, so any setoption is accepted and ignored
+            Some("setoption") | Some("debug") | Some("ponderhit") | None => {}
+            Some(cmd) => self
+                .out
+                .send(format!("info string unknown command {}", cmd)),
+        }
+        true
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop();
+        }
+    }
+
+    // position [startpos | fen <fen>] [moves <move>...]; the previous position
+    // never survives an error: a bad base falls back to the starting position
+    // and an illegal move keeps the moves before it
+    fn position(&mut self, tokens: &[&str]) -> Result<(), String> {
+        self.st = State::new();
+        let moves_at = tokens
+            .iter()
+            .position(|t| *t == "moves")
+            .unwrap_or(tokens.len());
+        match tokens.first() {
+            Some(&"startpos") => {}
+            Some(&"fen") => {
+                self.st = State::try_from_fen(&tokens[1..moves_at].join(" "))
+                    .map_err(|e| e.to_string())?
+            }
+            _ => return Err("expected startpos or fen".to_string()),
+        }
+        for mv in tokens.iter().skip(moves_at + 1) {
+            let mv = self.st.parse_uci(mv).map_err(|e| format!("{} {}", e, mv))?;
+            self.st.push(mv);
+        }
+        Ok(())
+    }
+
+    fn go(&mut self, tokens: &[&str]) {
+        let mut limits = SearchLimits::default();
+        let mut infinite = false;
+        let (mut wtime, mut btime, mut winc, mut binc, mut movestogo) = (None, None, 0, 0, None);
+        let mut iter = tokens.iter();
+        while let Some(token) = iter.next() {
+            let mut value = || iter.next().and_then(|v| v.parse::<u64>().ok());
+            match *token {
+                "depth" => limits.depth = value().map(|d| d as usize),
+                "nodes" => limits.nodes = value(),
+                "movetime" => limits.movetime = value().map(Duration::from_millis),
+                "wtime" => wtime = value(),
+                "btime" => btime = value(),
+                "winc" => winc = value().unwrap_or(0),
+                "binc" => binc = value().unwrap_or(0),
+                "movestogo" => movestogo = value(),
+                "infinite" => infinite = true,
+                _ => {}
+            }
+        }
+        let (time, inc) = match self.st.turn {
+            Color::White => (wtime, winc),
+            Color::Black => (btime, binc),
+        };
+        if let (Some(time), None) = (time, limits.movetime) {
+            limits.movetime = Some(allocate_time(
+                Duration::from_millis(time),
+                Duration::from_millis(inc),
+                movestogo,
+            ));
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut st = self.st.clone();
+        let out = self.out.clone();
+        let handle = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let best = search(&mut st, &limits, &stop, |info| print_info(info, &out));
+                // in infinite mode the bestmove must wait for stop
+                while infinite && !stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                match best {
+                    Some(mv) => out.send(format!("bestmove {}", mv.to_uci())),
+                    None => out.send("bestmove 0000"),
+                }
+            })
+        };
+        self.search = Some(Search { handle, stop });
+    }
+}
+
+fn print_info(info: &SearchInfo, out: &Output) {
+    let score = match info.mate_in() {
+        Some(n) => format!("mate {}", n),
+        None => format!("cp {}", info.score),
+    };
+    let millis = info.elapsed.as_millis().max(1) as u64;
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_uci()).collect();
+    out.send(format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        info.depth,
+        score,
+        info.nodes,
+        info.nodes * 1000 / millis,
+        millis,
+        pv.join(" ")
+    ));
+}
@@ -0,0 +1,279 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chess::{allocate_time, search, Color, Move, Outcome, SearchInfo, SearchLimits, State};
+
+use crate::output::Output;
+
+const ENGINE_NAME: &str = "rust-chess";
+
+// time control set by level, st or sd
+#[derive(Default)]
+struct TimeControl {
+    moves_per_session: u64,
+    increment: Duration,
+    per_move: Option<Duration>,
+    depth: Option<usize>,
+}
+
+// a search running in the background, which sends its own move when done
+struct Search {
+    handle: JoinHandle<Option<Move>>,
+    stop: Arc<AtomicBool>,
+    // checked under the lock before the move is sent, so an abandoned
+    // search never plays a move the front end does not know about
+    abandoned: Arc<Mutex<bool>>,
+}
+
+pub struct XBoard {
+    st: State,
+    // None while in force mode
+    engine_color: Option<Color>,
+    time_control: TimeControl,
+    // engine clock, as last reported by the time command
+    clock: Option<Duration>,
+    post: bool,
+    search: Option<Search>,
+    out: Output,
+}
+
+pub fn run(lines: impl Iterator<Item = String>) {
+    let mut xboard = XBoard::new(Output::Stdout);
+    for line in lines {
+        if !xboard.handle(&line) {
+            break;
+        }
+    }
+    xboard.finish_search(true);
+}
+
+impl XBoard {
+    pub fn new(out: Output) -> Self {
+        XBoard {
+            st: State::new(),
+            engine_color: Some(Color::Black),
+            time_control: TimeControl::default(),
+            clock: None,
+            post: false,
+            search: None,
+            out,
+        }
+    }
+
+    // returns false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let (cmd, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        // commands that change the game throw away a running search, the
+        // rest let it finish first so its move is played
+        match cmd {
+            "new" | "setboard" | "force" | "undo" | "remove" | "result" | "quit" => {
+                self.finish_search(true)
+            }
+            "usermove" | "go" | "?" => self.finish_search(false),
+            _ => {}
+        }
+        match cmd {
+            "protover" => self.out.send(format!(
+                "feature myname=\"{}\" setboard=1 usermove=1 ping=1 colors=0 analyze=0 sigint=0 sigterm=0 done=1",
+                ENGINE_NAME
+            )),
+            "new" => {
+                self.st = State::new();
+                self.engine_color = Some(Color::Black);
+                self.time_control.depth = None;
+                self.clock = None;
+            }
+            "setboard" => match State::try_from_fen(args) {
+                Ok(st) => self.st = st,
+                Err(e) => self.out.send(format!("tellusererror Illegal position: {}", e)),
+            },
+            "usermove" => {
+                let mv = self
+                    .st
+                    .parse_uci(args)
+                    .or_else(|_| self.st.parse_san(args).map_err(|_| ()));
+                match mv {
+                    Ok(mv) => {
+                        self.st.push(mv);
+                        if !print_result(&self.st, &self.out) {
+                            self.think();
+                        }
+                    }
+                    Err(_) => self.out.send(format!("Illegal move: {}", args)),
+                }
+            }
+            "go" => {
+                self.engine_color = Some(self.st.turn);
+                self.think();
+            }
+            // the game is over, so wait for new before playing again
+            "force" | "result" => self.engine_color = None,
+            "undo" => self.undo(1),
+            "remove" => self.undo(2),
+            "level" => self.level(args),
+            // negative, infinite or unparsable times are ignored
+            "st" => {
+                if let Some(per_move) = parse_seconds(args) {
+                    self.time_control.per_move = Some(per_move);
+                }
+            }
+            "sd" => self.time_control.depth = args.parse().ok(),
+            // a negative time means we are over the clock, so move at once
+            "time" => {
+                if let Ok(cs) = args.parse::<i64>() {
+                    self.clock = Some(Duration::from_millis(cs.max(0).saturating_mul(10) as u64));
+                }
+            }
+            "ping" => self.out.send(format!("pong {}", args)),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => return false,
+            // nothing to do for the rest of the protocol
+            _ => {}
+        }
+        true
+    }
+
+    fn undo(&mut self, plies: usize) {
+        for _ in 0..plies {
+            if self.st.reversions.is_empty() {
+                self.out.send("Error (no moves to undo): undo");
+                return;
+            }
+            self.st.pop();
+        }
+    }
+
+    // level MPS BASE INC, where BASE is minutes or minutes:seconds; the base
+    // time is our clock until the first time command
+    fn level(&mut self, args: &str) {
+        let fields: Vec<&str> = args.split_whitespace().collect();
+        if let [mps, base, inc] = fields[..] {
+            if let (Some(base), Some(increment)) = (parse_base(base), parse_seconds(inc)) {
+                self.time_control.moves_per_session = mps.parse().unwrap_or(0);
+                self.time_control.increment = increment;
+                self.time_control.per_move = None;
+                self.clock = Some(base);
+            }
+        }
+    }
+
+    // starts searching for our move in the background if it is our turn
+    fn think(&mut self) {
+        if self.engine_color != Some(self.st.turn) || self.st.outcome().is_some() {
+            return;
+        }
+        let tc = &self.time_control;
+        let movetime = tc.per_move.or_else(|| {
+            self.clock.map(|clock| {
+                let movestogo = (tc.moves_per_session > 0).then(|| {
+                    let played = (self.st.fullmove_number as u64 - 1) % tc.moves_per_session;
+                    tc.moves_per_session - played
+                });
+                allocate_time(clock, tc.increment, movestogo)
+            })
+        });
+        let limits = SearchLimits {
+            depth: tc.depth,
+            movetime,
+            ..Default::default()
+        };
+
+        let post = self.post;
+        let out = self.out.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let abandoned = Arc::new(Mutex::new(false));
+        let mut st = self.st.clone();
+        let handle = {
+            let (stop, abandoned) = (stop.clone(), abandoned.clone());
+            thread::spawn(move || {
+                let best = search(&mut st, &limits, &stop, |info| {
+                    if post {
+                        print_thinking(info, &out)
+                    }
+                });
+                let abandoned = abandoned.lock().unwrap();
+                match best {
+                    Some(mv) if !*abandoned => {
+                        out.send(format!("move {}", mv.to_uci()));
+                        st.push(mv);
+                        print_result(&st, &out);
+                        Some(mv)
+                    }
+                    _ => None,
+                }
+            })
+        };
+        self.search = Some(Search {
+            handle,
+            stop,
+            abandoned,
+        });
+    }
+
+    // stops the search and plays its move on our board, unless abandoned
+    fn finish_search(&mut self, abandon: bool) {
+        if let Some(search) = self.search.take() {
+            if abandon {
+                *search.abandoned.lock().unwrap() = true;
+            }
+            search.stop.store(true, Ordering::Relaxed);
+            if let Ok(Some(mv)) = search.handle.join() {
+                self.st.push(mv);
+            }
+        }
+    }
+}
+
+// prints the result if the game is over, returns whether it was
+fn print_result(st: &State, out: &Output) -> bool {
+    let result = match st.outcome() {
+        Some(Outcome::Checkmate {
+            winner: Color::White,
+        }) => "1-0 {White mates}",
+        Some(Outcome::Checkmate {
+            winner: Color::Black,
+        }) => "0-1 {Black mates}",
+        Some(Outcome::Stalemate) => "1/2-1/2 {Stalemate}",
+        Some(Outcome::InsufficientMaterial) => "1/2-1/2 {Insufficient material}",
+        Some(Outcome::FiftyMoveRule) | Some(Outcome::SeventyFiveMoveRule) => {
+            "1/2-1/2 {Draw by fifty move rule}"
+        }
+        Some(Outcome::ThreefoldRepetition) | Some(Outcome::FivefoldRepetition) => {
+            "1/2-1/2 {Draw by repetition}"
+        }
+        None => return false,
+    };
+    out.send(result);
+    true
+}
+
+fn parse_seconds(s: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(s.parse().ok()?).ok()
+}
+
+// minutes or minutes:seconds
+fn parse_base(s: &str) -> Option<Duration> {
+    let (minutes, seconds) = s.split_once(':').unwrap_or((s, "0"));
+    let seconds = minutes.parse::<u64>().ok()?.checked_mul(60)? + seconds.parse::<u64>().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+// ply score time nodes pv, with time in centiseconds
+fn print_thinking(info: &SearchInfo, out: &Output) {
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_uci()).collect();
+    out.send(format!(
+        "{} {} {} {} {}",
+        info.depth,
+        info.score,
+        info.elapsed.as_millis() / 10,
+        info.nodes,
+        pv.join(" ")
+    ));
+}
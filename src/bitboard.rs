@@ -1,9 +1,12 @@
-use crate::vector::Vector;
+use crate::{board::Color, vector::Vector};
 
 #[derive(Copy, Clone, Debug)]
-pub struct BitBoard(u64);
+pub struct BitBoard(pub(crate) u64);
 
 impl BitBoard {
+    // a1 is dark
+    pub const DARK_SQUARES: BitBoard = BitBoard(0xaa55_aa55_aa55_aa55);
+
     pub fn new_empty() -> BitBoard {
         BitBoard(0)
     }
@@ -32,6 +35,10 @@ impl BitBoard {
         BitBoard(self.0 & other.0)
     }
 
+    pub fn difference(&self, other: &BitBoard) -> BitBoard {
+        BitBoard(self.0 & !other.0)
+    }
+
     // creates a bitboard with a line from (x1, y1) to but not including (x2, y2)
     pub fn make_line(p1: Vector, p2: Vector) -> BitBoard {
         let mut line = BitBoard::new_empty();
@@ -52,4 +59,89 @@ impl BitBoard {
     pub fn lowest_set(&self) -> usize {
         self.0.trailing_zeros() as usize
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    // set squares from a1 to h8
+    pub fn iter(&self) -> BitBoardIter {
+        BitBoardIter(self.0)
+    }
+}
+
+pub struct BitBoardIter(u64);
+
+impl Iterator for BitBoardIter {
+    type Item = Vector;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let pos = Vector::from_num(self.0.trailing_zeros() as usize);
+        self.0 &= self.0 - 1;
+        Some(pos)
+    }
+}
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+// squares reached from each square by a single step, computed at compile time
+const fn step_attacks(steps: &[(i8, i8)]) -> [BitBoard; 64] {
+    let mut table = [BitBoard(0); 64];
+    let mut i = 0;
+    while i < 64 {
+        let (x, y) = ((i % 8) as i8, (i / 8) as i8);
+        let mut j = 0;
+        while j < steps.len() {
+            let (tx, ty) = (x + steps[j].0, y + steps[j].1);
+            if tx >= 0 && tx < 8 && ty >= 0 && ty < 8 {
+                table[i].0 |= 1 << (tx + 8 * ty);
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    table
+}
+
+static KNIGHT_ATTACKS: [BitBoard; 64] = step_attacks(&KNIGHT_STEPS);
+static KING_ATTACKS: [BitBoard; 64] = step_attacks(&KING_STEPS);
+// indexed by the color of the attacking pawn
+static PAWN_ATTACKS: [[BitBoard; 64]; 2] = [
+    step_attacks(&[(-1, 1), (1, 1)]),
+    step_attacks(&[(-1, -1), (1, -1)]),
+];
+
+pub fn knight_attacks(pos: Vector) -> BitBoard {
+    KNIGHT_ATTACKS[pos.as_num()]
+}
+
+pub fn king_attacks(pos: Vector) -> BitBoard {
+    KING_ATTACKS[pos.as_num()]
+}
+
+// diagonal captures only, pushes depend on the occupancy
+pub fn pawn_attacks(pos: Vector, color: Color) -> BitBoard {
+    PAWN_ATTACKS[color as usize][pos.as_num()]
 }
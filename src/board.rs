@@ -1,6 +1,6 @@
 use std::{fmt::Display, slice::Iter};
 
-use crate::vector::Vector;
+use crate::{bitboard::BitBoard, vector::Vector};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Soldier {
@@ -12,7 +12,21 @@ pub enum Soldier {
     King,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+impl Soldier {
+    // uppercase letter used by FEN and SAN
+    pub fn letter(&self) -> char {
+        match self {
+            Soldier::Pawn => 'P',
+            Soldier::Knight => 'N',
+            Soldier::Bishop => 'B',
+            Soldier::Rook => 'R',
+            Soldier::Queen => 'Q',
+            Soldier::King => 'K',
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Color {
     White,
     Black,
@@ -29,32 +43,80 @@ impl Color {
 
 pub type Piece = (Soldier, Color);
 
+// mailbox for square lookups, kept in sync with bitboards for set-wise queries
 #[derive(Clone)]
-pub struct Board([Option<Piece>; 64]);
+pub struct Board {
+    squares: [Option<Piece>; 64],
+    soldiers: [BitBoard; 6],
+    colors: [BitBoard; 2],
+    kings: [Option<Vector>; 2],
+}
 
 impl Board {
     pub fn new() -> Board {
-        Board([None; 64])
+        Board {
+            squares: [None; 64],
+            soldiers: [BitBoard::new_empty(); 6],
+            colors: [BitBoard::new_empty(); 2],
+            kings: [None; 2],
+        }
     }
 
-    pub fn iter(&self) -> Iter<Option<Piece>> {
-        self.0.iter()
+    pub fn iter(&self) -> Iter<'_, Option<Piece>> {
+        self.squares.iter()
     }
 
     pub fn get(&self, pos: Vector) -> &Option<Piece> {
-        &self.0[pos.as_num()]
+        &self.squares[pos.as_num()]
     }
 
     pub fn set(&mut self, pos: Vector, piece: Option<Piece>) -> Option<Piece> {
-        let prev = self.0[pos.as_num()];
-        self.0[pos.as_num()] = piece;
+        let prev = self.squares[pos.as_num()];
+        if let Some((s, c)) = prev {
+            self.soldiers[s as usize].unset(pos);
+            self.colors[c as usize].unset(pos);
+            if s == Soldier::King {
+                self.kings[c as usize] = None;
+            }
+        }
+        if let Some((s, c)) = piece {
+            self.soldiers[s as usize].set(pos);
+            self.colors[c as usize].set(pos);
+            if s == Soldier::King {
+                self.kings[c as usize] = Some(pos);
+            }
+        }
+        self.squares[pos.as_num()] = piece;
         prev
     }
 
     pub fn remove(&mut self, pos: Vector) -> Option<Piece> {
-        let piece = self.0[pos.as_num()];
-        self.set(pos, None);
-        piece
+        self.set(pos, None)
+    }
+
+    pub fn occupancy(&self) -> BitBoard {
+        self.colors[0].union(&self.colors[1])
+    }
+
+    pub fn color_occupancy(&self, color: Color) -> BitBoard {
+        self.colors[color as usize]
+    }
+
+    // squares of a soldier type, of both colors
+    pub fn soldiers(&self, s: Soldier) -> BitBoard {
+        self.soldiers[s as usize]
+    }
+
+    pub fn pieces(&self, (s, c): Piece) -> BitBoard {
+        self.soldiers[s as usize].intersection(&self.colors[c as usize])
+    }
+
+    pub fn count(&self, piece: Piece) -> u32 {
+        self.pieces(piece).num_set()
+    }
+
+    pub fn king_pos(&self, color: Color) -> Option<Vector> {
+        self.kings[color as usize]
     }
 
     pub fn is_same_color(&self, p1: Vector, p2: Vector) -> bool {
@@ -72,15 +134,9 @@ impl Board {
     }
 
     pub fn remove_king(&mut self, color: Color) -> Vector {
-        for v in Vector::board_pos_iter() {
-            if let Some((Soldier::King, c)) = self.get(v) {
-                if *c == color {
-                    self.set(v, None);
-                    return v;
-                }
-            }
-        }
-        unreachable!("King not on board")
+        let kv = self.king_pos(color).expect("King not on board");
+        self.remove(kv);
+        kv
     }
 
     pub fn is_sliding_piece(p: &Option<Piece>) -> bool {
@@ -95,6 +151,12 @@ impl Board {
     }
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut board = String::new();
@@ -1,23 +1,182 @@
+use std::fmt::Display;
+
 use crate::{
+    bitboard::BitBoard,
     board::{Board, Color, Piece, Soldier},
     state::State,
     vector::Vector,
 };
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FenField {
+    Placement,
+    Side,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber,
+}
+
+// index is always the character offset within the failing field
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(FenField),
+    TooManyFields,
+    UnexpectedEnd(FenField),
+    InvalidChar {
+        field: FenField,
+        index: usize,
+        found: char,
+    },
+    RankOverflow {
+        rank: i8,
+        index: usize,
+    },
+    RankUnderflow {
+        rank: i8,
+        index: usize,
+    },
+    TooManyRanks {
+        index: usize,
+    },
+    TooFewRanks {
+        found: usize,
+    },
+    InvalidKingCount {
+        color: Color,
+        found: usize,
+    },
+    PawnOnBackRank(Vector),
+    // more than 8 pawns, or more promoted pieces than missing pawns
+    TooMuchMaterial(Color),
+    // the right is given, but the king or rook is not on its home square
+    InvalidCastlingRight(char),
+    // not behind a pawn of the side that just moved, or on the wrong rank
+    InvalidEnPassantSquare(Vector),
+    // the side not to move is in check, so its king could be captured
+    OpponentInCheck,
+    FullmoveNumberZero,
+}
+
+impl Display for FenField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FenField::Placement => "piece placement",
+            FenField::Side => "side to move",
+            FenField::Castling => "castling rights",
+            FenField::EnPassant => "en passant square",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveNumber => "fullmove number",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {} field", field),
+            FenError::TooManyFields => write!(f, "too many fields"),
+            FenError::UnexpectedEnd(field) => write!(f, "unexpected end of {}", field),
+            FenError::InvalidChar {
+                field,
+                index,
+                found,
+            } => write!(f, "invalid character '{}' at {} in {}", found, index, field),
+            FenError::RankOverflow { rank, index } => {
+                write!(f, "rank {} has more than 8 squares at {}", rank, index)
+            }
+            FenError::RankUnderflow { rank, index } => {
+                write!(f, "rank {} has fewer than 8 squares at {}", rank, index)
+            }
+            FenError::TooManyRanks { index } => write!(f, "more than 8 ranks at {}", index),
+            FenError::TooFewRanks { found } => write!(f, "expected 8 ranks, found {}", found),
+            FenError::InvalidKingCount { color, found } => {
+                write!(f, "expected one {:?} king, found {}", color, found)
+            }
+            FenError::PawnOnBackRank(pos) => write!(f, "pawn on back rank at {}", pos),
+            FenError::TooMuchMaterial(color) => {
+                write!(f, "{:?} has more material than a game can produce", color)
+            }
+            FenError::InvalidCastlingRight(c) => {
+                write!(
+                    f,
+                    "castling right '{}' without king and rook on their squares",
+                    c
+                )
+            }
+            FenError::InvalidEnPassantSquare(pos) => {
+                write!(f, "en passant square {} does not follow a double push", pos)
+            }
+            FenError::OpponentInCheck => write!(f, "side not to move is in check"),
+            FenError::FullmoveNumberZero => write!(f, "fullmove number must be at least 1"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
 impl State {
     pub fn from_fen(fen: &str) -> Self {
-        let mut split_fen = fen.split(' ');
-        let board = get_board(split_fen.next().unwrap());
-        let turn = get_active_color(split_fen.next().unwrap());
+        match Self::try_from_fen(fen) {
+            Ok(st) => st,
+            Err(e) => panic!("Invalid FEN '{}': {}", fen, e),
+        }
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.fen().to_string()
+    }
+
+    // formats the state as FEN without allocating, e.g. for use with write!
+    pub fn fen(&self) -> Fen<'_> {
+        Fen(self)
+    }
+
+    pub fn try_from_fen(fen: &str) -> Result<Self, FenError> {
+        let mut split_fen = fen.split_whitespace();
+        let mut next_field = |field| split_fen.next().ok_or(FenError::MissingField(field));
+        let board = get_board(next_field(FenField::Placement)?)?;
+        let turn = get_active_color(next_field(FenField::Side)?)?;
         let (
             white_castle_kingside,
             white_castle_queenside,
             black_castle_kingside,
             black_castle_queenside,
-        ) = castling_rights(split_fen.next().unwrap());
-        let en_passant_square = get_en_passant_square(split_fen.next().unwrap());
+        ) = castling_rights(next_field(FenField::Castling)?)?;
+        let en_passant_square = get_en_passant_square(next_field(FenField::EnPassant)?)?;
 
-        State {
+        // the clocks are optional, but must be well formed if present
+        let (halfmove_clock, fullmove_number) = match split_fen.next() {
+            Some(s) => (
+                get_clock(s, FenField::HalfmoveClock)?,
+                get_clock(
+                    split_fen
+                        .next()
+                        .ok_or(FenError::MissingField(FenField::FullmoveNumber))?,
+                    FenField::FullmoveNumber,
+                )?,
+            ),
+            None => (0, 1),
+        };
+        if split_fen.next().is_some() {
+            return Err(FenError::TooManyFields);
+        }
+
+        validate(
+            &board,
+            turn,
+            [
+                white_castle_kingside,
+                white_castle_queenside,
+                black_castle_kingside,
+                black_castle_queenside,
+            ],
+            en_passant_square,
+            fullmove_number,
+        )?;
+
+        let mut st = State {
             board,
             turn,
             white_castle_kingside,
@@ -25,91 +184,312 @@ impl State {
             black_castle_kingside,
             black_castle_queenside,
             en_passant_square,
+            halfmove_clock,
+            fullmove_number,
+            zobrist: 0,
             reversions: Vec::new(),
+        };
+        st.zobrist = st.compute_zobrist();
+        Ok(st)
+    }
+}
+
+// rejects well formed FENs describing positions move generation cannot handle
+fn validate(
+    board: &Board,
+    turn: Color,
+    castling: [bool; 4],
+    en_passant_square: Option<Vector>,
+    fullmove_number: u32,
+) -> Result<(), FenError> {
+    let back_rank_pawns = board
+        .soldiers(Soldier::Pawn)
+        .intersection(&BitBoard(0xff00_0000_0000_00ff));
+    if let Some(pos) = back_rank_pawns.iter().next() {
+        return Err(FenError::PawnOnBackRank(pos));
+    }
+
+    // every piece beyond the starting set must be a promoted pawn, which also
+    // keeps the number of moves within what a move list can hold
+    for color in [Color::White, Color::Black] {
+        let count = |s| board.pieces((s, color)).num_set();
+        let pawns = count(Soldier::Pawn);
+        let promoted: u32 = [
+            (Soldier::Knight, 2),
+            (Soldier::Bishop, 2),
+            (Soldier::Rook, 2),
+            (Soldier::Queen, 1),
+        ]
+        .iter()
+        .map(|&(s, start)| count(s).saturating_sub(start))
+        .sum();
+        if pawns + promoted > 8 {
+            return Err(FenError::TooMuchMaterial(color));
+        }
+    }
+
+    // indexed as K, Q, k, q, with the king and rook home squares
+    let homes = [
+        ('K', Color::White, 0, 7),
+        ('Q', Color::White, 0, 0),
+        ('k', Color::Black, 7, 7),
+        ('q', Color::Black, 7, 0),
+    ];
+    for (&allowed, &(c, color, y, rook_x)) in castling.iter().zip(&homes) {
+        if allowed
+            && (*board.get(Vector::new(4, y)) != Some((Soldier::King, color))
+                || *board.get(Vector::new(rook_x, y)) != Some((Soldier::Rook, color)))
+        {
+            return Err(FenError::InvalidCastlingRight(c));
+        }
+    }
+
+    // the pawn that just moved two squares sits in front of the en passant
+    // square, with the square itself and the one it came from empty
+    if let Some(ev) = en_passant_square {
+        let (rank, dy) = if turn == Color::White {
+            (5, -1)
+        } else {
+            (2, 1)
+        };
+        let pawn = Vector::new(ev.x, ev.y + dy);
+        let start = Vector::new(ev.x, ev.y - dy);
+        if ev.y != rank
+            || *board.get(pawn) != Some((Soldier::Pawn, turn.opposite()))
+            || board.get(ev).is_some()
+            || board.get(start).is_some()
+        {
+            return Err(FenError::InvalidEnPassantSquare(ev));
+        }
+    }
+
+    if board.is_attacked(board.king_pos(turn.opposite()).unwrap(), turn) {
+        return Err(FenError::OpponentInCheck);
+    }
+    if fullmove_number == 0 {
+        return Err(FenError::FullmoveNumberZero);
+    }
+    Ok(())
+}
+
+pub struct Fen<'a>(&'a State);
+
+impl Display for Fen<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let st = self.0;
+        for row in (0..=7).rev() {
+            let mut empty = 0;
+            for col in 0..=7 {
+                match st.board.get(Vector::new(col, row)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            write!(f, "{}", empty)?;
+                            empty = 0;
+                        }
+                        write!(f, "{}", piece_to_char(*piece))?;
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                write!(f, "{}", empty)?;
+            }
+            if row != 0 {
+                write!(f, "/")?;
+            }
+        }
+
+        let side = if st.turn == Color::White { 'w' } else { 'b' };
+        write!(f, " {} ", side)?;
+
+        let rights = [
+            (st.white_castle_kingside, 'K'),
+            (st.white_castle_queenside, 'Q'),
+            (st.black_castle_kingside, 'k'),
+            (st.black_castle_queenside, 'q'),
+        ];
+        if rights.iter().all(|(allowed, _)| !allowed) {
+            write!(f, "-")?;
+        }
+        for (allowed, c) in rights {
+            if allowed {
+                write!(f, "{}", c)?;
+            }
         }
+
+        match st.en_passant_square {
+            Some(ev) => write!(f, " {}", ev)?,
+            None => write!(f, " -")?,
+        }
+
+        write!(f, " {} {}", st.halfmove_clock, st.fullmove_number)
     }
 }
 
-fn get_board(piece_field: &str) -> Board {
+fn get_board(piece_field: &str) -> Result<Board, FenError> {
     let mut board = Board::new();
     let mut row = 7;
     let mut col = 0;
-    for c in piece_field.chars() {
+    let mut kings = [0, 0];
+    for (i, c) in piece_field.chars().enumerate() {
         if c == '/' {
+            if col < 8 {
+                return Err(FenError::RankUnderflow {
+                    rank: row + 1,
+                    index: i,
+                });
+            }
+            if row == 0 {
+                return Err(FenError::TooManyRanks { index: i });
+            }
             row -= 1;
             col = 0;
             continue;
         }
 
-        if c.is_ascii_digit() {
+        if ('1'..='8').contains(&c) {
             col += c.to_digit(10).unwrap() as i8;
         } else {
-            let piece = char_to_piece(c);
-            board.set(Vector::new(col, row), Some(piece));
+            let piece = char_to_piece(c).ok_or(FenError::InvalidChar {
+                field: FenField::Placement,
+                index: i,
+                found: c,
+            })?;
+            if col < 8 {
+                board.set(Vector::new(col, row), Some(piece));
+            }
+            if let (Soldier::King, color) = piece {
+                kings[color as usize] += 1;
+            }
             col += 1;
         }
+        if col > 8 {
+            return Err(FenError::RankOverflow {
+                rank: row + 1,
+                index: i,
+            });
+        }
     }
-    board
+    if row > 0 {
+        return Err(FenError::TooFewRanks {
+            found: (8 - row) as usize,
+        });
+    }
+    if col < 8 {
+        return Err(FenError::RankUnderflow {
+            rank: 1,
+            index: piece_field.chars().count(),
+        });
+    }
+    for color in [Color::White, Color::Black] {
+        if kings[color as usize] != 1 {
+            return Err(FenError::InvalidKingCount {
+                color,
+                found: kings[color as usize],
+            });
+        }
+    }
+    Ok(board)
 }
 
-fn char_to_piece(c: char) -> Piece {
+fn char_to_piece(c: char) -> Option<Piece> {
     match c {
-        'r' => (Soldier::Rook, Color::Black),
-        'n' => (Soldier::Knight, Color::Black),
-        'b' => (Soldier::Bishop, Color::Black),
-        'q' => (Soldier::Queen, Color::Black),
-        'k' => (Soldier::King, Color::Black),
-        'p' => (Soldier::Pawn, Color::Black),
-        'R' => (Soldier::Rook, Color::White),
-        'N' => (Soldier::Knight, Color::White),
-        'B' => (Soldier::Bishop, Color::White),
-        'Q' => (Soldier::Queen, Color::White),
-        'K' => (Soldier::King, Color::White),
-        'P' => (Soldier::Pawn, Color::White),
-        _ => unreachable!("Invalid piece"),
+        'r' => Some((Soldier::Rook, Color::Black)),
+        'n' => Some((Soldier::Knight, Color::Black)),
+        'b' => Some((Soldier::Bishop, Color::Black)),
+        'q' => Some((Soldier::Queen, Color::Black)),
+        'k' => Some((Soldier::King, Color::Black)),
+        'p' => Some((Soldier::Pawn, Color::Black)),
+        'R' => Some((Soldier::Rook, Color::White)),
+        'N' => Some((Soldier::Knight, Color::White)),
+        'B' => Some((Soldier::Bishop, Color::White)),
+        'Q' => Some((Soldier::Queen, Color::White)),
+        'K' => Some((Soldier::King, Color::White)),
+        'P' => Some((Soldier::Pawn, Color::White)),
+        _ => None,
     }
 }
 
-fn get_active_color(s: &str) -> Color {
+fn piece_to_char((s, c): Piece) -> char {
+    match c {
+        Color::White => s.letter(),
+        Color::Black => s.letter().to_ascii_lowercase(),
+    }
+}
+
+fn get_active_color(s: &str) -> Result<Color, FenError> {
     match s {
-        "w" => Color::White,
-        "b" => Color::Black,
-        _ => unreachable!("Invalid active color"),
+        "w" => Ok(Color::White),
+        "b" => Ok(Color::Black),
+        _ => Err(invalid_char(s, FenField::Side, |i, c| {
+            i > 0 || !matches!(c, 'w' | 'b')
+        })),
     }
 }
 
-fn castling_rights(s: &str) -> (bool, bool, bool, bool) {
-    let mut kingside_white = false;
-    let mut queenside_white = false;
-    let mut kingside_black = false;
-    let mut queenside_black = false;
-
-    for c in s.chars() {
-        match c {
-            'K' => kingside_white = true,
-            'Q' => queenside_white = true,
-            'k' => kingside_black = true,
-            'q' => queenside_black = true,
-            '-' => break,
-            _ => unreachable!("Invalid castling rights"),
+fn castling_rights(s: &str) -> Result<(bool, bool, bool, bool), FenError> {
+    // indexed as K, Q, k, q
+    let mut rights = [false; 4];
+    if s == "-" {
+        return Ok((false, false, false, false));
+    }
+
+    for (i, c) in s.chars().enumerate() {
+        let right = match c {
+            'K' => 0,
+            'Q' => 1,
+            'k' => 2,
+            'q' => 3,
+            _ => 4,
+        };
+        if right == 4 || rights[right] {
+            return Err(FenError::InvalidChar {
+                field: FenField::Castling,
+                index: i,
+                found: c,
+            });
         }
+        rights[right] = true;
     }
 
-    (
-        kingside_white,
-        queenside_white,
-        kingside_black,
-        queenside_black,
-    )
+    Ok((rights[0], rights[1], rights[2], rights[3]))
 }
 
-fn get_en_passant_square(s: &str) -> Option<Vector> {
+fn get_en_passant_square(s: &str) -> Result<Option<Vector>, FenError> {
     if s == "-" {
-        None
-    } else {
-        let mut chars = s.chars();
-        let file = chars.next().unwrap() as i8 - 97;
-        let rank = chars.next().unwrap().to_digit(10).unwrap() as i8 - 1;
-        Some(Vector::new(file, rank))
+        return Ok(None);
+    }
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() != 2 || !('a'..='h').contains(&chars[0]) || !matches!(chars[1], '3' | '6') {
+        return Err(invalid_char(s, FenField::EnPassant, |i, c| match i {
+            0 => !('a'..='h').contains(&c),
+            1 => !matches!(c, '3' | '6'),
+            _ => true,
+        }));
+    }
+    let file = chars[0] as i8 - 97;
+    let rank = chars[1].to_digit(10).unwrap() as i8 - 1;
+    Ok(Some(Vector::new(file, rank)))
+}
+
+fn get_clock(s: &str, field: FenField) -> Result<u32, FenError> {
+    // any non-digit is reported first, otherwise the number is too large
+    s.parse().map_err(|_| {
+        invalid_char(s, field, |i, c| {
+            !c.is_ascii_digit() || i == s.chars().count() - 1
+        })
+    })
+}
+
+// reports the first character of the field matching the predicate, or the
+// end of the field if it was cut short
+fn invalid_char(s: &str, field: FenField, pred: impl Fn(usize, char) -> bool) -> FenError {
+    match s.chars().enumerate().find(|&(i, c)| pred(i, c)) {
+        Some((index, found)) => FenError::InvalidChar {
+            field,
+            index,
+            found,
+        },
+        None => FenError::UnexpectedEnd(field),
     }
 }
@@ -0,0 +1,35 @@
+mod attacks;
+mod bitboard;
+mod board;
+mod fen;
+mod magic;
+mod moves;
+mod outcome;
+mod packed;
+mod perft;
+mod san;
+mod search;
+mod state;
+mod test;
+mod uci;
+mod vector;
+mod zobrist;
+
+pub use bitboard::{king_attacks, knight_attacks, pawn_attacks, BitBoard};
+pub use board::{Board, Color, Piece, Soldier};
+pub use fen::{Fen, FenError, FenField};
+pub use moves::{
+    generate_moves, gives_check, has_legal_move, legal_moves, legal_moves_into, num_legal_moves,
+    GenType, LegalMoves, Move, MoveList, MAX_MOVES,
+};
+pub use outcome::Outcome;
+pub use packed::{IntoPacked, MoveFlag, PackedMove};
+pub use perft::{
+    divide, divide_parallel, perft, perft_hashed, perft_parallel, perft_stats,
+    perft_stats_by_depth, PerftStats, PerftTable,
+};
+pub use san::SanError;
+pub use search::{allocate_time, evaluate, search, SearchInfo, SearchLimits, MATE_SCORE};
+pub use state::{Reversion, State};
+pub use uci::UciError;
+pub use vector::{ParseSquareError, Vector};
@@ -0,0 +1,159 @@
+use std::sync::OnceLock;
+
+use crate::{bitboard::BitBoard, vector::Vector};
+
+// fancy magic bitboards: for each square the relevant blockers are multiplied by a
+// magic number and shifted down to index that square's slice of a shared attack table
+#[derive(Copy, Clone, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct SlidingTables {
+    bishop: [Magic; 64],
+    rook: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+static TABLES: OnceLock<SlidingTables> = OnceLock::new();
+
+// generated on first use, which takes a few tens of milliseconds
+fn tables() -> &'static SlidingTables {
+    TABLES.get_or_init(|| {
+        let mut attacks = Vec::new();
+        let bishop = find_magics(&Vector::bishop_dirs(), &mut attacks);
+        let rook = find_magics(&Vector::rook_dirs(), &mut attacks);
+        SlidingTables {
+            bishop,
+            rook,
+            attacks,
+        }
+    })
+}
+
+pub fn bishop_attacks(pos: Vector, occupied: BitBoard) -> BitBoard {
+    let tables = tables();
+    BitBoard(tables.attacks[tables.bishop[pos.as_num()].index(occupied.0)])
+}
+
+pub fn rook_attacks(pos: Vector, occupied: BitBoard) -> BitBoard {
+    let tables = tables();
+    BitBoard(tables.attacks[tables.rook[pos.as_num()].index(occupied.0)])
+}
+
+pub fn queen_attacks(pos: Vector, occupied: BitBoard) -> BitBoard {
+    bishop_attacks(pos, occupied).union(&rook_attacks(pos, occupied))
+}
+
+fn find_magics(dirs: &[Vector], attacks: &mut Vec<u64>) -> [Magic; 64] {
+    let mut magics = [Magic::default(); 64];
+    for (i, magic) in magics.iter_mut().enumerate() {
+        let pos = Vector::from_num(i);
+        let mask = relevant_blockers(pos, dirs);
+        let bits = mask.count_ones();
+
+        // every subset of the mask with its attack set
+        let mut blockers = Vec::with_capacity(1 << bits);
+        let mut subset: u64 = 0;
+        loop {
+            blockers.push((subset, ray_attacks(pos, dirs, subset)));
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+
+        let offset = attacks.len();
+        attacks.resize(offset + (1 << bits), 0);
+        let mut used = vec![0u32; 1 << bits];
+        let mut rng = XorShift(SEEDS[pos.y as usize]);
+        let mut attempt = 0;
+        *magic = loop {
+            let magic = rng.next_sparse();
+            // cheap filter: the mask must spread into the high bits used as the index
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue;
+            }
+            attempt += 1;
+            let candidate = Magic {
+                mask,
+                magic,
+                shift: 64 - bits,
+                offset,
+            };
+            // a candidate must not map two subsets with different attacks together,
+            // the attempt number marks which slots this candidate has filled
+            let fits = blockers.iter().all(|&(occupied, attacked)| {
+                let slot = candidate.index(occupied) - offset;
+                if used[slot] != attempt {
+                    used[slot] = attempt;
+                    attacks[candidate.index(occupied)] = attacked;
+                    true
+                } else {
+                    attacks[candidate.index(occupied)] == attacked
+                }
+            });
+            if fits {
+                break candidate;
+            }
+        };
+    }
+    magics
+}
+
+// squares along the rays whose occupancy matters, which excludes the board edge
+fn relevant_blockers(pos: Vector, dirs: &[Vector]) -> u64 {
+    let mut mask = 0;
+    for &dir in dirs {
+        let mut curr = pos + dir;
+        while (curr + dir).in_bounds() {
+            mask |= 1 << curr.as_num();
+            curr = curr + dir;
+        }
+    }
+    mask
+}
+
+// attacks found by walking each ray until it hits a blocker
+fn ray_attacks(pos: Vector, dirs: &[Vector], occupied: u64) -> u64 {
+    let mut attacks = 0;
+    for &dir in dirs {
+        let mut curr = pos + dir;
+        while curr.in_bounds() {
+            attacks |= 1 << curr.as_num();
+            if occupied & (1 << curr.as_num()) != 0 {
+                break;
+            }
+            curr = curr + dir;
+        }
+    }
+    attacks
+}
+
+// per rank seeds known to find magics quickly with this generator
+const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // magics with few bits set are found much faster
+    fn next_sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
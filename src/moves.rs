@@ -1,8 +1,15 @@
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min},
+    fmt::{self, Debug, Formatter},
+    ops::{Deref, DerefMut},
+    slice::Iter,
+};
 
 use crate::{
-    bitboard::BitBoard,
+    bitboard::{king_attacks, knight_attacks, pawn_attacks, BitBoard},
     board::{Board, Color, Soldier},
+    magic::{bishop_attacks, queen_attacks, rook_attacks},
+    packed::{MoveFlag, PackedMove},
     state::State,
     vector::Vector,
 };
@@ -14,72 +21,305 @@ pub struct Move {
     pub promotion: Option<Soldier>,
 }
 
-impl Move {
-    fn from_bitboard(s: Soldier, from: Vector, to: BitBoard) -> Vec<Self> {
-        let mut moves = Vec::new();
-        for pos in Vector::board_pos_iter() {
-            if to.get(pos) {
-                if matches!(s, Soldier::Pawn) && (pos.y == 0 || pos.y == 7) {
-                    moves.append(&mut Self::promotion_moves(from, pos));
-                } else {
-                    moves.push(Self {
-                        from,
-                        to: pos,
-                        promotion: None,
-                    });
+// adds a move to each target square, with the flags read off the board so
+// that playing the move later needs no further lookups
+fn push_moves(st: &State, s: Soldier, from: Vector, to: BitBoard, moves: &mut MoveList) {
+    let enemies = st.board.color_occupancy(st.turn.opposite());
+    for pos in to.iter() {
+        let capture = enemies.get(pos);
+        let flag = match s {
+            Soldier::Pawn if pos.y == 0 || pos.y == 7 => {
+                for p in &GEN_PROMOTIONS {
+                    moves.push(PackedMove::new(from, pos, MoveFlag::promotion(*p, capture)));
                 }
+                continue;
             }
+            Soldier::Pawn if Some(pos) == st.en_passant_square => MoveFlag::EnPassant,
+            Soldier::Pawn if (pos.y - from.y).abs() == 2 => MoveFlag::DoublePush,
+            Soldier::King if pos.x - from.x == 2 => MoveFlag::KingCastle,
+            Soldier::King if pos.x - from.x == -2 => MoveFlag::QueenCastle,
+            _ if capture => MoveFlag::Capture,
+            _ => MoveFlag::Quiet,
+        };
+        moves.push(PackedMove::new(from, pos, flag));
+    }
+}
+
+// promotions in the order they are generated, best first
+const GEN_PROMOTIONS: [Soldier; 4] = [
+    Soldier::Queen,
+    Soldier::Rook,
+    Soldier::Bishop,
+    Soldier::Knight,
+];
+
+// positions reachable in a game have at most 218 moves, but FENs only limit
+// material: a king with castling (10), the starting queen, rooks, bishops and
+// knights (27 + 2 * 14 + 2 * 13 + 2 * 8) and eight pawns or promoted pieces,
+// each at most a queen (8 * 27)
+pub const MAX_MOVES: usize = 10 + 27 + 2 * 14 + 2 * 13 + 2 * 8 + 8 * 27;
+
+// fixed-capacity move buffer that lives on the stack, so generating moves
+// never allocates
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [PackedMove; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        let filler = PackedMove::new(Vector::new(0, 0), Vector::new(0, 0), MoveFlag::Quiet);
+        MoveList {
+            moves: [filler; MAX_MOVES],
+            len: 0,
         }
-        moves
-    }
-
-    fn promotion_moves(from: Vector, to: Vector) -> Vec<Move> {
-        let mut moves = Vec::new();
-        for s in &[
-            Soldier::Queen,
-            Soldier::Rook,
-            Soldier::Bishop,
-            Soldier::Knight,
-        ] {
-            moves.push(Self {
-                from,
-                to,
-                promotion: Some(*s),
-            });
+    }
+
+    pub fn push(&mut self, mv: PackedMove) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // keeps the moves matching the predicate, in their original order
+    pub fn retain(&mut self, mut keep: impl FnMut(&PackedMove) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
         }
-        moves
+        self.len = kept;
     }
 }
 
-pub fn legal_moves(st: &State) -> Vec<Move> {
-    // get the enemy's pseudo-legal moves without our king (so we can check attacked squares behind king)
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [PackedMove];
+
+    fn deref(&self) -> &[PackedMove] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [PackedMove] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a PackedMove;
+    type IntoIter = Iter<'a, PackedMove>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Debug for MoveList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// enemy attacks on our side, computed with our king removed from the board
+struct KingDanger {
+    opp_board: Board,
+    king_pos: Vector,
+    attacked_squares: BitBoard,
+    num_checkers: usize,
+    checker_pos: Option<Vector>,
+}
+
+fn king_danger(st: &State) -> KingDanger {
+    // remove our king so the squares behind it count as attacked
     let opp_turn = st.turn.opposite();
     let mut opp_board = st.board.clone();
     let kv = opp_board.remove_king(st.turn);
-    let opp_moves = pseudo_legal_moves(&opp_board, opp_turn, None);
-
-    // figure out the number of checkers, their position, and the attacked squares bitboard
-    let mut checker_pos = None;
-    let mut num_checkers = 0;
-    let mut attacked_squares = BitBoard::new_empty();
-    opp_moves.iter().enumerate().for_each(|(i, mvs)| {
-        if let Some(mr) = mvs {
-            let pv = Vector::from_num(i);
-            let (s, _) = opp_board.get(pv).unwrap();
-            let attacks = if matches!(s, Soldier::Pawn) {
-                mr.attacks // only care about diagonal pawn attacks
+
+    let checkers = opp_board.attackers_to(kv, opp_turn, opp_board.occupancy());
+    let attacked_squares = opp_board.attack_map(opp_turn);
+    let num_checkers = checkers.num_set() as usize;
+    let checker_pos = checkers.iter().next();
+
+    KingDanger {
+        opp_board,
+        king_pos: kv,
+        attacked_squares,
+        num_checkers,
+        checker_pos,
+    }
+}
+
+pub fn num_checkers(st: &State) -> usize {
+    checkers(st).num_set() as usize
+}
+
+// squares of the enemy pieces giving check to the side to move
+pub fn checkers(st: &State) -> BitBoard {
+    match st.board.king_pos(st.turn) {
+        Some(kv) => st
+            .board
+            .attackers_to(kv, st.turn.opposite(), st.board.occupancy()),
+        None => BitBoard::new_empty(),
+    }
+}
+
+pub fn legal_moves(st: &State) -> Vec<Move> {
+    let mut moves = MoveList::new();
+    legal_moves_into(st, &mut moves);
+    moves.iter().map(|&mv| mv.into()).collect()
+}
+
+// fills the list with the legal moves, replacing whatever it held
+pub fn legal_moves_into(st: &State, moves: &mut MoveList) {
+    generate_moves(st, GenType::All, moves);
+}
+
+// the number of legal moves, counted from the target masks without listing
+// the moves, for perft leaves
+pub fn num_legal_moves(st: &State) -> usize {
+    let masks = legal_masks(st);
+    masks
+        .movers(st)
+        .iter()
+        .map(|pos| {
+            let (s, targets) = masks.targets(st, pos);
+            let promotions = if s == Soldier::Pawn {
+                targets.intersection(&PROMOTION_RANKS).num_set()
             } else {
-                mr.moves.union(&mr.attacks)
+                0
             };
-            if attacks.get(kv) {
-                num_checkers += 1;
-                checker_pos = Some(pv);
-            }
-            attacked_squares = attacked_squares.union(&attacks);
+            // each promotion square is four moves
+            (targets.num_set() + 3 * promotions) as usize
+        })
+        .sum()
+}
+
+// which legal moves to generate, so search can look at the likely best ones
+// first and skip the rest after a cutoff
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GenType {
+    // captures, including en passant, and all promotions
+    Captures,
+    // everything that is not in Captures, castling included
+    Quiets,
+    // every legal move when in check, nothing otherwise
+    Evasions,
+    // the quiet moves that give check
+    QuietChecks,
+    All,
+}
+
+const PROMOTION_RANKS: BitBoard = BitBoard(0xff00_0000_0000_00ff);
+
+// fills the list with the legal moves of the given type, replacing whatever it held
+pub fn generate_moves(st: &State, gen: GenType, moves: &mut MoveList) {
+    moves.clear();
+    generate_legal(st, gen, moves);
+    if gen == GenType::QuietChecks {
+        moves.retain(|mv| gives_check(st, *mv));
+    }
+}
+
+fn generate_legal(st: &State, gen: GenType, moves: &mut MoveList) {
+    let masks = legal_masks(st);
+    if gen == GenType::Evasions && masks.num_checkers == 0 {
+        return;
+    }
+
+    // squares that make a move a capture or a promotion, the rest are quiet
+    let enemies = st.board.color_occupancy(st.turn.opposite());
+    let mut pawn_noisy = enemies.union(&PROMOTION_RANKS);
+    if let Some(ev) = st.en_passant_square {
+        pawn_noisy.set(ev);
+    }
+    let stage = |s: Soldier, to: BitBoard| {
+        let noisy = if s == Soldier::Pawn {
+            pawn_noisy
+        } else {
+            enemies
+        };
+        match gen {
+            GenType::Captures => to.intersection(&noisy),
+            GenType::Quiets | GenType::QuietChecks => to.difference(&noisy),
+            GenType::Evasions | GenType::All => to,
+        }
+    };
+
+    for pos in masks.movers(st).iter() {
+        let (s, targets) = masks.targets(st, pos);
+        push_moves(st, s, pos, stage(s, targets), moves);
+    }
+}
+
+// the check and pin masks every move of a position is filtered through
+struct LegalMasks {
+    king_pos: Vector,
+    king_moves: BitBoard,
+    num_checkers: usize,
+    checker_mask: BitBoard,
+    // also lets pawns take a checking pawn en passant
+    pawn_checker_mask: BitBoard,
+    pinned_mask: [BitBoard; 64],
+}
+
+impl LegalMasks {
+    // pieces that may have legal moves, only the king when in double check
+    fn movers(&self, st: &State) -> BitBoard {
+        if self.num_checkers > 1 {
+            let mut king = BitBoard::new_empty();
+            king.set(self.king_pos);
+            king
+        } else {
+            st.board.color_occupancy(st.turn)
+        }
+    }
+
+    // legal destinations of our piece on pos
+    fn targets(&self, st: &State, pos: Vector) -> (Soldier, BitBoard) {
+        let (s, _) = st.board.get(pos).unwrap();
+        if pos == self.king_pos {
+            // we already found king moves
+            return (s, self.king_moves);
         }
-    });
+        let checker_mask = if s == Soldier::Pawn {
+            self.pawn_checker_mask
+        } else {
+            self.checker_mask
+        };
+        let mr = piece_moves(&st.board, pos, st.turn, st.en_passant_square);
+        let targets = mr
+            .moves
+            .intersection(&checker_mask)
+            .intersection(&self.pinned_mask[pos.as_num()]);
+        (s, targets)
+    }
+}
+
+fn legal_masks(st: &State) -> LegalMasks {
+    let opp_turn = st.turn.opposite();
+    let KingDanger {
+        mut opp_board,
+        king_pos: kv,
+        attacked_squares,
+        num_checkers,
+        checker_pos,
+        ..
+    } = king_danger(st);
 
-    // get our king moves based on the attacked squares, return if double check
+    // get our king moves based on the attacked squares, nothing else can move if double check
     // if single check, create checker mask representing only legal squares in position
     let (kingside_rights, queenside_rights) = st.castling_rights_for_color();
     let king_moves = king_moves(
@@ -90,79 +330,289 @@ pub fn legal_moves(st: &State) -> Vec<Move> {
         kingside_rights,
         queenside_rights,
     );
+    let mut masks = LegalMasks {
+        king_pos: kv,
+        king_moves: king_moves.moves,
+        num_checkers,
+        checker_mask: BitBoard::new_empty(),
+        pawn_checker_mask: BitBoard::new_empty(),
+        pinned_mask: [BitBoard::new_full(); 64],
+    };
     if num_checkers > 1 {
-        return Move::from_bitboard(Soldier::King, kv, king_moves.moves);
+        return masks;
     }
-    let checker_mask = if num_checkers == 1 {
+    masks.checker_mask = if num_checkers == 1 {
         get_checker_mask(&st.board, checker_pos.unwrap(), kv)
     } else {
         BitBoard::new_full() // every square is legal
     };
+    masks.pawn_checker_mask = masks.checker_mask;
+    if let (Some(ev), Some(cv)) = (st.en_passant_square, checker_pos) {
+        let (cs, _) = st.board.get(cv).unwrap();
+        if matches!(cs, Soldier::Pawn) {
+            // if we are in check from a pawn that we can
+            // enpassant, allow the enpassant square as a legal move
+            masks.pawn_checker_mask.set(ev);
+        }
+    }
 
     // put an enemy queen where our king was to help with computing pins
     opp_board.set(kv, Some((Soldier::Queen, opp_turn)));
-    let pin_lines_from_king = sliding_moves(&opp_board, kv, Soldier::Queen, opp_turn);
+    let pin_lines_from_king = sliding_moves(
+        kv,
+        Soldier::Queen,
+        opp_board.occupancy(),
+        opp_board.color_occupancy(opp_turn),
+    );
 
     // for each enemy slider in line with our king, see if it pins a piece to our king
     // if so, narrow it's entry in the pinned mask to only the legal pinned moves
-    let mut pinned_mask = [BitBoard::new_full(); 64];
-    for (i, (piece, moves)) in st.board.iter().zip(opp_moves.iter()).enumerate() {
-        let pos = Vector::from_num(i);
-        if moves.is_none() || !Board::is_sliding_piece(piece) || !Board::is_aligned(pos, kv) {
+    let opp_sliders = st
+        .board
+        .soldiers(Soldier::Bishop)
+        .union(&st.board.soldiers(Soldier::Rook))
+        .union(&st.board.soldiers(Soldier::Queen))
+        .intersection(&st.board.color_occupancy(opp_turn));
+    for pos in opp_sliders.iter() {
+        if !Board::is_aligned(pos, kv) {
             continue;
         }
-        let mr = moves.as_ref().unwrap();
+        let mr = piece_moves(&st.board, pos, opp_turn, None);
         let line = BitBoard::make_line(pos, kv); // line joining enemy slider to king
         let pinned = line
             .intersection(&mr.moves)
             .intersection(&pin_lines_from_king.moves);
 
         if pinned.num_set() == 1 {
-            pinned_mask[pinned.lowest_set()] = line;
+            masks.pinned_mask[pinned.lowest_set()] = line;
         }
     }
     if let Some(ev) = st.en_passant_square {
         let dy = if st.turn == Color::White { -1 } else { 1 };
         if is_enpassant_pin_rank(&st.board, st.turn, ev.y + dy) {
             let col = ((ev.y + dy) * 8) as usize;
-            pinned_mask[col + ev.x as usize + 1].unset(ev);
-            pinned_mask[col + ev.x as usize - 1].unset(ev);
+            masks.pinned_mask[col + ev.x as usize + 1].unset(ev);
+            masks.pinned_mask[col + ev.x as usize - 1].unset(ev);
         }
     }
+    masks
+}
 
-    pseudo_legal_moves(&st.board, st.turn, st.en_passant_square)
-        .iter()
-        .enumerate()
-        .filter_map(|(i, mr)| {
-            if let Some(mr) = mr {
-                let pos = Vector::from_num(i);
-                let (s, _) = st.board.get(pos).unwrap();
-                if pos == kv {
-                    // we already found king moves
-                    return Some(Move::from_bitboard(s, kv, king_moves.moves));
-                }
-                let mut checker_mask = checker_mask;
-                if let Some(ev) = st.en_passant_square {
-                    if let Some(cv) = checker_pos {
-                        let (cs, _) = st.board.get(cv).unwrap();
-                        if matches!(cs, Soldier::Pawn) && matches!(s, Soldier::Pawn) {
-                            // if we are in check from a pawn that we can
-                            // enpassant, allow the enpassant square as a legal move
-                            checker_mask.set(ev);
-                        }
-                    }
+// legal moves produced one piece at a time, for callers that may stop early;
+// the masks are computed once up front
+pub struct LegalMoves<'a> {
+    st: &'a State,
+    masks: LegalMasks,
+    movers: BitBoard,
+    from: Vector,
+    soldier: Soldier,
+    targets: BitBoard,
+    // promotion square and the index of the next piece to promote to
+    promotion: Option<(Vector, usize)>,
+}
+
+impl<'a> LegalMoves<'a> {
+    pub fn new(st: &'a State) -> Self {
+        let masks = legal_masks(st);
+        LegalMoves {
+            st,
+            movers: masks.movers(st),
+            masks,
+            from: Vector::new(0, 0),
+            soldier: Soldier::Pawn,
+            targets: BitBoard::new_empty(),
+            promotion: None,
+        }
+    }
+}
+
+impl Iterator for LegalMoves<'_> {
+    type Item = PackedMove;
+
+    // same order as legal_moves
+    fn next(&mut self) -> Option<PackedMove> {
+        loop {
+            if let Some((to, i)) = self.promotion {
+                self.promotion = (i + 1 < GEN_PROMOTIONS.len()).then_some((to, i + 1));
+                return Some(self.st.pack(Move {
+                    from: self.from,
+                    to,
+                    promotion: Some(GEN_PROMOTIONS[i]),
+                }));
+            }
+            if let Some(to) = self.targets.iter().next() {
+                self.targets.unset(to);
+                if self.soldier == Soldier::Pawn && (to.y == 0 || to.y == 7) {
+                    self.promotion = Some((to, 0));
+                    continue;
                 }
-                let legal_moves = mr
-                    .moves
-                    .intersection(&checker_mask)
-                    .intersection(&pinned_mask[i]);
-                Some(Move::from_bitboard(s, pos, legal_moves))
+                return Some(self.st.pack(Move {
+                    from: self.from,
+                    to,
+                    promotion: None,
+                }));
+            }
+            let from = self.movers.iter().next()?;
+            self.movers.unset(from);
+            self.from = from;
+            (self.soldier, self.targets) = self.masks.targets(self.st, from);
+        }
+    }
+}
+
+// stops at the first legal move found
+pub fn has_legal_move(st: &State) -> bool {
+    LegalMoves::new(st).next().is_some()
+}
+
+impl State {
+    // whether the move follows the movement rules of the piece on its from
+    // square, ignoring whether it would leave our king in check
+    pub fn is_pseudo_legal(&self, mv: Move) -> bool {
+        if !mv.from.in_bounds() || !mv.to.in_bounds() {
+            return false;
+        }
+        let s = match *self.board.get(mv.from) {
+            Some((s, c)) if c == self.turn => s,
+            _ => return false,
+        };
+
+        // a promotion piece is given exactly when a pawn reaches the last rank
+        let promotes = s == Soldier::Pawn && (mv.to.y == 0 || mv.to.y == 7);
+        match mv.promotion {
+            None if promotes => return false,
+            Some(p) if !promotes || !GEN_PROMOTIONS.contains(&p) => return false,
+            _ => {}
+        }
+
+        if s == Soldier::King && mv.from.y == mv.to.y && (mv.to.x - mv.from.x).abs() == 2 {
+            // castling needs the right, the rook in its corner and nothing in between
+            let (kingside, queenside) = self.castling_rights_for_color();
+            let (allowed, rook_x) = if mv.to.x > mv.from.x {
+                (kingside, 7)
             } else {
-                None
+                (queenside, 0)
+            };
+            let rook = Vector::new(rook_x, mv.from.y);
+            return allowed
+                && *self.board.get(rook) == Some((Soldier::Rook, self.turn))
+                && BitBoard::make_line(rook, mv.from)
+                    .intersection(&self.board.occupancy())
+                    .num_set()
+                    == 1;
+        }
+        piece_moves(&self.board, mv.from, self.turn, self.en_passant_square)
+            .moves
+            .get(mv.to)
+    }
+
+    // checks a single move against the check, pin, castling and en passant
+    // rules without generating the other moves
+    pub fn is_legal(&self, mv: Move) -> bool {
+        if !self.is_pseudo_legal(mv) {
+            return false;
+        }
+        let them = self.turn.opposite();
+        let ksq = match self.board.king_pos(self.turn) {
+            Some(ksq) => ksq,
+            None => return true,
+        };
+        let mut occupied = self.board.occupancy();
+
+        if mv.from == ksq {
+            if (mv.to.x - mv.from.x).abs() == 2 {
+                // castling: not out of, through or into check
+                let step = (mv.to.x - mv.from.x).signum();
+                return (0..3).all(|i| {
+                    let pos = Vector::new(ksq.x + i * step, ksq.y);
+                    self.board.attackers_to(pos, them, occupied).is_empty()
+                });
             }
-        })
-        .flatten()
-        .collect()
+            // the king must not hide behind itself from a slider
+            occupied.unset(ksq);
+            return self.board.attackers_to(mv.to, them, occupied).is_empty();
+        }
+
+        // any other move: the enemy pieces still attacking our king once the
+        // board is as it will be after the move, which covers pins, blocks and
+        // the en passant rank pin; a captured checker no longer counts
+        occupied.unset(mv.from);
+        occupied.set(mv.to);
+        let (s, _) = self.board.get(mv.from).unwrap();
+        if s == Soldier::Pawn && mv.from.x != mv.to.x && self.board.get(mv.to).is_none() {
+            occupied.unset(Vector::new(mv.to.x, mv.from.y));
+        }
+        let mut checkers = self.board.attackers_to(ksq, them, occupied);
+        checkers.unset(mv.to);
+        checkers.is_empty()
+    }
+}
+
+// whether the move checks the enemy king, either directly or by uncovering one
+// of our sliders, found from the bitboards as they will be after the move
+pub fn gives_check(st: &State, mv: PackedMove) -> bool {
+    let flag = mv.flag();
+    let mv = Move::from(mv);
+    let ksq = match st.board.king_pos(st.turn.opposite()) {
+        Some(ksq) => ksq,
+        None => return false,
+    };
+    let (moved, _) = st.board.get(mv.from).unwrap();
+    let s = mv.promotion.unwrap_or(moved);
+    let own = st.board.color_occupancy(st.turn);
+    let mut occupied = st.board.occupancy();
+    let mut diagonal = st
+        .board
+        .soldiers(Soldier::Bishop)
+        .union(&st.board.soldiers(Soldier::Queen))
+        .intersection(&own);
+    let mut straight = st
+        .board
+        .soldiers(Soldier::Rook)
+        .union(&st.board.soldiers(Soldier::Queen))
+        .intersection(&own);
+    occupied.unset(mv.from);
+    occupied.set(mv.to);
+    diagonal.unset(mv.from);
+    straight.unset(mv.from);
+    match s {
+        Soldier::Bishop => diagonal.set(mv.to),
+        Soldier::Rook => straight.set(mv.to),
+        Soldier::Queen => {
+            diagonal.set(mv.to);
+            straight.set(mv.to);
+        }
+        _ => {}
+    }
+
+    match flag {
+        MoveFlag::EnPassant => occupied.unset(Vector::new(mv.to.x, mv.from.y)),
+        MoveFlag::KingCastle | MoveFlag::QueenCastle => {
+            let (rook_from, rook_to) = if flag == MoveFlag::KingCastle {
+                (Vector::new(7, mv.from.y), Vector::new(5, mv.from.y))
+            } else {
+                (Vector::new(0, mv.from.y), Vector::new(3, mv.from.y))
+            };
+            occupied.unset(rook_from);
+            occupied.set(rook_to);
+            straight.unset(rook_from);
+            straight.set(rook_to);
+        }
+        _ => {}
+    }
+
+    let direct = match s {
+        Soldier::Pawn => pawn_attacks(mv.to, st.turn),
+        Soldier::Knight => knight_attacks(mv.to),
+        _ => BitBoard::new_empty(),
+    };
+    direct.get(ksq)
+        || !bishop_attacks(ksq, occupied)
+            .intersection(&diagonal)
+            .is_empty()
+        || !rook_attacks(ksq, occupied)
+            .intersection(&straight)
+            .is_empty()
 }
 
 // given a board and a position of an enemy piece that is checking the king,
@@ -191,27 +641,31 @@ fn is_enpassant_pin_rank(bd: &Board, side: Color, rank: i8) -> bool {
     // the rank containing the enpassantable pawn should look like:
     // [enemy slider ... pawn pawn ... king] or [king ... pawn pawn ... enemy slider]
 
-    let mut soldiers = Vec::new();
+    // at most 8 pieces on a rank, kept on the stack
+    let mut soldiers = [Soldier::Pawn; 8];
+    let mut len = 0;
     let mut king_file = None;
     for file in 0..=7 {
         if let Some(p) = bd.get(Vector::new(file, rank)) {
             match p {
                 (Soldier::Rook, c) | (Soldier::Queen, c) => {
                     if *c != side {
-                        soldiers.push(Soldier::Rook)
+                        soldiers[len] = Soldier::Rook
                     } else {
-                        soldiers.push(Soldier::Queen)
+                        soldiers[len] = Soldier::Queen
                     }
                 }
                 (s, c) => {
                     if *c == side && matches!(s, Soldier::King) {
-                        king_file = Some(soldiers.len())
+                        king_file = Some(len)
                     }
-                    soldiers.push(*s)
+                    soldiers[len] = *s
                 }
             }
+            len += 1;
         }
     }
+    let soldiers = &soldiers[..len];
     if king_file.is_none() {
         return false;
     }
@@ -228,88 +682,49 @@ fn is_enpassant_pin_rank(bd: &Board, side: Color, rank: i8) -> bool {
     )
 }
 
-fn sliding_piece_directions(s: &Soldier) -> Vec<Vector> {
-    match s {
-        Soldier::Bishop => Vector::bishop_dirs(),
-        Soldier::Rook => Vector::rook_dirs(),
-        Soldier::Queen => Vector::queen_dirs(),
-        _ => panic!("Not a sliding piece"),
-    }
-}
-
+#[derive(Copy, Clone)]
 struct MovesResult {
     moves: BitBoard,
-    attacks: BitBoard, // squares attacked (same-color capture, diagonal pawn, etc.)
 }
 
-fn pseudo_legal_moves(
+fn piece_moves(
     bd: &Board,
+    pos: Vector,
     side: Color,
     enpassant_square: Option<Vector>,
-) -> Vec<Option<MovesResult>> {
-    let mut move_sets: Vec<Option<MovesResult>> = Vec::new();
-    for (i, &square) in bd.iter().enumerate() {
-        let pos = Vector::from_num(i);
-        if square.is_none() || square.unwrap().1 != side {
-            move_sets.push(None);
-            continue;
-        }
-        let soldier = square.unwrap().0;
-        match soldier {
-            Soldier::Pawn => move_sets.push(Some(pawn_moves(bd, pos, side, enpassant_square))),
-            Soldier::Knight => move_sets.push(Some(knight_moves(bd, pos))),
-            Soldier::Bishop | Soldier::Rook | Soldier::Queen => {
-                move_sets.push(Some(sliding_moves(bd, pos, soldier, side)))
-            }
-            // don't worry about castling here, it happens in legal move generation
-            Soldier::King => move_sets.push(Some(king_moves(
-                bd,
-                pos,
-                BitBoard::new_empty(),
-                true,
-                false,
-                false,
-            ))),
+) -> MovesResult {
+    let occupied = bd.occupancy();
+    let own = bd.color_occupancy(side);
+    let (soldier, _) = bd.get(pos).unwrap();
+    match soldier {
+        Soldier::Pawn => pawn_moves(bd, pos, side, enpassant_square),
+        Soldier::Knight => knight_moves(pos, own),
+        Soldier::Bishop | Soldier::Rook | Soldier::Queen => {
+            sliding_moves(pos, soldier, occupied, own)
         }
+        // don't worry about castling here, it happens in legal move generation
+        Soldier::King => king_moves(bd, pos, BitBoard::new_empty(), true, false, false),
     }
-    move_sets
-}
-
-fn sliding_moves(bd: &Board, pos: Vector, soldier: Soldier, side: Color) -> MovesResult {
-    let mut attacks = BitBoard::new_empty();
-    let mut moves = BitBoard::new_empty();
-    let dirs = sliding_piece_directions(&soldier);
-    for dir in dirs {
-        let mut curr = pos + dir;
-        while curr.in_bounds() {
-            attacks.set(curr);
-            match bd.get(curr) {
-                Some((_, c)) if c == &side => break,
-                Some(_) => {
-                    moves.set(curr);
-                    break;
-                }
-                None => moves.set(curr),
-            }
-            curr = curr + dir;
-        }
+}
+
+fn sliding_moves(pos: Vector, soldier: Soldier, occupied: BitBoard, own: BitBoard) -> MovesResult {
+    // attacks include the first blocker in every direction, whatever its color
+    let attacks = match soldier {
+        Soldier::Bishop => bishop_attacks(pos, occupied),
+        Soldier::Rook => rook_attacks(pos, occupied),
+        Soldier::Queen => queen_attacks(pos, occupied),
+        _ => panic!("Not a sliding piece"),
+    };
+    MovesResult {
+        moves: attacks.difference(&own),
     }
-    MovesResult { attacks, moves }
 }
 
-fn knight_moves(bd: &Board, pos: Vector) -> MovesResult {
-    let mut attacks = BitBoard::new_empty();
-    let mut moves = BitBoard::new_empty();
-    Vector::knight_dirs().iter().for_each(|dir| {
-        let offset = pos + *dir;
-        if offset.in_bounds() {
-            attacks.set(offset);
-            if !bd.is_same_color(pos, offset) {
-                moves.set(offset);
-            }
-        }
-    });
-    MovesResult { attacks, moves }
+fn knight_moves(pos: Vector, own: BitBoard) -> MovesResult {
+    let attacks = knight_attacks(pos);
+    MovesResult {
+        moves: attacks.difference(&own),
+    }
 }
 
 fn pawn_moves(
@@ -318,8 +733,8 @@ fn pawn_moves(
     side: Color,
     enpassant_square: Option<Vector>,
 ) -> MovesResult {
-    let mut attacks = BitBoard::new_empty();
-    let mut moves = BitBoard::new_empty();
+    let attacks = pawn_attacks(pos, side);
+    let mut moves = attacks.intersection(&bd.color_occupancy(side.opposite()));
     let push_dir = Vector::new(0, if side == Color::White { 1 } else { -1 });
     let is_start = if side == Color::White {
         pos.y == 1
@@ -332,23 +747,12 @@ fn pawn_moves(
             moves.set(pos + push_dir + push_dir);
         }
     };
-    for dx in [-1, 1].iter() {
-        let dir = Vector::new(*dx, push_dir.y);
-        let offset = pos + dir;
-        if offset.in_bounds() {
-            attacks.set(offset); // label diagonal as a capture so it is picked up as attacked
-            if bd.is_enemy_color(pos, offset) {
-                moves.set(offset);
-            }
-        }
-        if let Some(ev) = enpassant_square {
-            if offset == ev {
-                moves.set(offset);
-                attacks.set(Vector::new(pos.x + dx, pos.y));
-            }
+    if let Some(ev) = enpassant_square {
+        if attacks.get(ev) {
+            moves.set(ev);
         }
     }
-    MovesResult { attacks, moves }
+    MovesResult { moves }
 }
 
 fn king_moves(
@@ -359,17 +763,11 @@ fn king_moves(
     kingside_rights: bool,
     queenside_rights: bool,
 ) -> MovesResult {
-    let mut attacks = BitBoard::new_empty();
-    let mut moves = BitBoard::new_empty();
-    Vector::king_dirs().iter().for_each(|dir| {
-        let offset = pos + *dir;
-        if offset.in_bounds() {
-            attacks.set(offset);
-            if !bd.is_same_color(pos, offset) && !attacked.get(offset) {
-                moves.set(offset);
-            }
-        }
-    });
+    let attacks = king_attacks(pos);
+    let (_, side) = bd.get(pos).unwrap();
+    let mut moves = attacks
+        .difference(&bd.color_occupancy(side))
+        .difference(&attacked);
 
     if !king_in_check {
         if kingside_rights
@@ -391,5 +789,5 @@ fn king_moves(
         }
     }
 
-    MovesResult { attacks, moves }
+    MovesResult { moves }
 }
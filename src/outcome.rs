@@ -0,0 +1,84 @@
+use crate::{
+    bitboard::BitBoard,
+    board::{Color, Soldier},
+    moves::{has_legal_move, num_checkers},
+    state::State,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Checkmate { winner: Color },
+    Stalemate,
+    // 75 moves without a capture or pawn move ends the game automatically,
+    // 50 only gives the right to claim a draw
+    SeventyFiveMoveRule,
+    FiftyMoveRule,
+    FivefoldRepetition,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+}
+
+impl Outcome {
+    pub fn winner(&self) -> Option<Color> {
+        match self {
+            Outcome::Checkmate { winner } => Some(*winner),
+            _ => None,
+        }
+    }
+}
+
+impl State {
+    pub fn is_check(&self) -> bool {
+        num_checkers(self) > 0
+    }
+
+    // returns the outcome of the game if it is over (or a draw can be claimed),
+    // mandatory endings take precedence over claimable ones
+    pub fn outcome(&self) -> Option<Outcome> {
+        if !has_legal_move(self) {
+            return if self.is_check() {
+                Some(Outcome::Checkmate {
+                    winner: self.turn.opposite(),
+                })
+            } else {
+                Some(Outcome::Stalemate)
+            };
+        }
+
+        let repetitions = self.repetition_count();
+        if self.halfmove_clock >= 150 {
+            Some(Outcome::SeventyFiveMoveRule)
+        } else if repetitions >= 5 {
+            Some(Outcome::FivefoldRepetition)
+        } else if self.is_insufficient_material() {
+            Some(Outcome::InsufficientMaterial)
+        } else if self.halfmove_clock >= 100 {
+            Some(Outcome::FiftyMoveRule)
+        } else if repetitions >= 3 {
+            Some(Outcome::ThreefoldRepetition)
+        } else {
+            None
+        }
+    }
+
+    // neither side can checkmate: only kings and at most one minor piece,
+    // or only bishops that all stand on the same square color
+    pub fn is_insufficient_material(&self) -> bool {
+        let bd = &self.board;
+        let mating_material = bd
+            .soldiers(Soldier::Pawn)
+            .union(&bd.soldiers(Soldier::Rook))
+            .union(&bd.soldiers(Soldier::Queen));
+        if !mating_material.is_empty() {
+            return false;
+        }
+        let bishops = bd.soldiers(Soldier::Bishop);
+        let on_dark = !bishops.intersection(&BitBoard::DARK_SQUARES).is_empty();
+        let on_light = !bishops.difference(&BitBoard::DARK_SQUARES).is_empty();
+        match bd.soldiers(Soldier::Knight).num_set() {
+            0 => !(on_dark && on_light),
+            1 => bishops.is_empty(),
+            _ => false,
+        }
+    }
+}
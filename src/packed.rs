@@ -0,0 +1,148 @@
+use crate::{board::Soldier, moves::Move, state::State, vector::Vector};
+
+// what kind of move it is, stored in the top 4 bits of a packed move: bit 2 is
+// set on captures and bit 3 on promotions, with the promoted piece in bits 0-1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MoveFlag {
+    Quiet = 0,
+    DoublePush = 1,
+    KingCastle = 2,
+    QueenCastle = 3,
+    Capture = 4,
+    EnPassant = 5,
+    KnightPromotion = 8,
+    BishopPromotion = 9,
+    RookPromotion = 10,
+    QueenPromotion = 11,
+    KnightPromotionCapture = 12,
+    BishopPromotionCapture = 13,
+    RookPromotionCapture = 14,
+    QueenPromotionCapture = 15,
+}
+
+// promotions in the order of the two piece bits in the move flag
+const FLAG_PROMOTIONS: [Soldier; 4] = [
+    Soldier::Knight,
+    Soldier::Bishop,
+    Soldier::Rook,
+    Soldier::Queen,
+];
+
+impl MoveFlag {
+    fn from_bits(bits: u16) -> MoveFlag {
+        match bits {
+            0 => MoveFlag::Quiet,
+            1 => MoveFlag::DoublePush,
+            2 => MoveFlag::KingCastle,
+            3 => MoveFlag::QueenCastle,
+            4 => MoveFlag::Capture,
+            5 => MoveFlag::EnPassant,
+            8 => MoveFlag::KnightPromotion,
+            9 => MoveFlag::BishopPromotion,
+            10 => MoveFlag::RookPromotion,
+            11 => MoveFlag::QueenPromotion,
+            12 => MoveFlag::KnightPromotionCapture,
+            13 => MoveFlag::BishopPromotionCapture,
+            14 => MoveFlag::RookPromotionCapture,
+            15 => MoveFlag::QueenPromotionCapture,
+            _ => unreachable!("unused move flag {}", bits),
+        }
+    }
+
+    pub fn promotion(s: Soldier, capture: bool) -> MoveFlag {
+        let piece = FLAG_PROMOTIONS
+            .iter()
+            .position(|&p| p == s)
+            .unwrap_or_else(|| panic!("cannot promote to {:?}", s));
+        Self::from_bits(8 | (capture as u16) << 2 | piece as u16)
+    }
+}
+
+// a move in 16 bits: from square in bits 0-5, to square in bits 6-11 and the
+// flag in bits 12-15
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    pub fn new(from: Vector, to: Vector, flag: MoveFlag) -> Self {
+        PackedMove(from.as_num() as u16 | (to.as_num() as u16) << 6 | (flag as u16) << 12)
+    }
+
+    pub fn from(&self) -> Vector {
+        Vector::from_num((self.0 & 0x3f) as usize)
+    }
+
+    pub fn to(&self) -> Vector {
+        Vector::from_num((self.0 >> 6 & 0x3f) as usize)
+    }
+
+    pub fn flag(&self) -> MoveFlag {
+        MoveFlag::from_bits(self.0 >> 12)
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.0 & 1 << 14 != 0
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        self.0 & 1 << 15 != 0
+    }
+
+    pub fn is_castle(&self) -> bool {
+        matches!(self.flag(), MoveFlag::KingCastle | MoveFlag::QueenCastle)
+    }
+
+    pub fn promotion(&self) -> Option<Soldier> {
+        self.is_promotion()
+            .then(|| FLAG_PROMOTIONS[(self.0 >> 12 & 3) as usize])
+    }
+}
+
+impl From<PackedMove> for Move {
+    fn from(mv: PackedMove) -> Move {
+        Move {
+            from: mv.from(),
+            to: mv.to(),
+            promotion: mv.promotion(),
+        }
+    }
+}
+
+// anything that can be played on a state: plain moves are packed against the
+// position first, packed moves already carry their flags
+pub trait IntoPacked {
+    fn into_packed(self, st: &State) -> PackedMove;
+}
+
+impl IntoPacked for Move {
+    fn into_packed(self, st: &State) -> PackedMove {
+        st.pack(self)
+    }
+}
+
+impl IntoPacked for PackedMove {
+    fn into_packed(self, _: &State) -> PackedMove {
+        self
+    }
+}
+
+impl State {
+    // the flags depend on the position, so packing needs the state the move
+    // is played from
+    pub fn pack(&self, mv: Move) -> PackedMove {
+        let (s, _) = self.board.get(mv.from).expect("no piece to move");
+        let capture = self.board.get(mv.to).is_some();
+        let flag = match (s, mv.promotion) {
+            (_, Some(p)) => MoveFlag::promotion(p, capture),
+            (Soldier::King, _) if mv.to.x - mv.from.x == 2 => MoveFlag::KingCastle,
+            (Soldier::King, _) if mv.to.x - mv.from.x == -2 => MoveFlag::QueenCastle,
+            (Soldier::Pawn, _) if (mv.to.y - mv.from.y).abs() == 2 => MoveFlag::DoublePush,
+            (Soldier::Pawn, _) if Some(mv.to) == self.en_passant_square && mv.from.x != mv.to.x => {
+                MoveFlag::EnPassant
+            }
+            _ if capture => MoveFlag::Capture,
+            _ => MoveFlag::Quiet,
+        };
+        PackedMove::new(mv.from, mv.to, flag)
+    }
+}
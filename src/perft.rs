@@ -0,0 +1,291 @@
+use std::{
+    ops::AddAssign,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    moves::{
+        checkers, has_legal_move, legal_moves, legal_moves_into, num_legal_moves, Move, MoveList,
+    },
+    packed::{MoveFlag, PackedMove},
+    state::State,
+    vector::Vector,
+};
+
+// number of leaf nodes of the legal move tree at the given depth
+pub fn perft(st: &mut State, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut moves = MoveList::new();
+    legal_moves_into(st, &mut moves);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut num_nodes = 0;
+    for &mv in &moves {
+        st.push(mv);
+        num_nodes += perft(st, depth - 1);
+        st.pop();
+    }
+    num_nodes
+}
+
+// fixed-size cache of perft results, indexed by position key; slots come in
+// pairs, one keeping the deepest result seen and one always replaced, so the
+// expensive deep counts survive the flood of shallow ones
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+    mask: usize,
+}
+
+// the depth lives in the low byte of data and the node count above it, which
+// keeps an entry at 16 bytes
+#[derive(Copy, Clone, Default)]
+struct PerftEntry {
+    zobrist: u64,
+    data: u64,
+}
+
+impl PerftEntry {
+    fn depth(&self) -> usize {
+        (self.data & 0xff) as usize
+    }
+}
+
+impl PerftTable {
+    // the number of entries is rounded down to a power of two, at least one pair
+    pub fn new(entries: usize) -> Self {
+        let size = if entries.is_power_of_two() {
+            entries
+        } else {
+            entries.next_power_of_two() / 2
+        }
+        .max(2);
+        PerftTable {
+            entries: vec![PerftEntry::default(); size],
+            mask: size - 2,
+        }
+    }
+
+    pub fn with_megabytes(mb: usize) -> Self {
+        Self::new(mb * 1024 * 1024 / std::mem::size_of::<PerftEntry>())
+    }
+
+    fn slots(&self, zobrist: u64) -> usize {
+        zobrist as usize & self.mask
+    }
+
+    fn get(&self, zobrist: u64, depth: usize) -> Option<u64> {
+        let i = self.slots(zobrist);
+        // depth 0 is never stored, so empty slots never match
+        self.entries[i..i + 2]
+            .iter()
+            .find(|e| e.zobrist == zobrist && e.depth() == depth)
+            .map(|e| e.data >> 8)
+    }
+
+    fn insert(&mut self, zobrist: u64, depth: usize, nodes: u64) {
+        let i = self.slots(zobrist);
+        let entry = PerftEntry {
+            zobrist,
+            data: nodes << 8 | depth as u64,
+        };
+        if depth >= self.entries[i].depth() {
+            self.entries[i] = entry;
+        } else {
+            self.entries[i + 1] = entry;
+        }
+    }
+}
+
+// perft that reuses the counts of transposed positions
+pub fn perft_hashed(st: &mut State, depth: usize, table: &mut PerftTable) -> u64 {
+    match depth {
+        0 => return 1,
+        1 => return num_legal_moves(st) as u64,
+        _ => {}
+    }
+    if let Some(nodes) = table.get(st.zobrist, depth) {
+        return nodes;
+    }
+    let mut moves = MoveList::new();
+    legal_moves_into(st, &mut moves);
+    let mut num_nodes = 0;
+    for &mv in &moves {
+        st.push(mv);
+        num_nodes += perft_hashed(st, depth - 1, table);
+        st.pop();
+    }
+    table.insert(st.zobrist, depth, num_nodes);
+    num_nodes
+}
+
+// perft split by root move, for finding which move a wrong count comes from
+pub fn divide(st: &mut State, depth: usize) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    legal_moves(st)
+        .into_iter()
+        .map(|mv| {
+            st.push(mv);
+            let nodes = perft(st, depth - 1);
+            st.pop();
+            (mv, nodes)
+        })
+        .collect()
+}
+
+// divide with the root moves shared out between threads, each searching
+// its own copy of the state
+pub fn divide_parallel(st: &State, depth: usize, threads: usize) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let moves = legal_moves(st);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(moves.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, moves.len().max(1)) {
+            scope.spawn(|| {
+                let mut st = st.clone();
+                // threads take the next unclaimed root move until none are left
+                while let Some(&mv) = moves.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    st.push(mv);
+                    let nodes = perft(&mut st, depth - 1);
+                    st.pop();
+                    results.lock().unwrap().push((mv, nodes));
+                }
+            });
+        }
+    });
+
+    // keep the same order as the serial divide
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(mv, _)| moves.iter().position(|m| m == mv));
+    results
+}
+
+pub fn perft_parallel(st: &State, depth: usize, threads: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    divide_parallel(st, depth, threads)
+        .iter()
+        .map(|(_, nodes)| nodes)
+        .sum()
+}
+
+// breakdown of the moves leading to the leaf nodes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub discovered_checks: u64,
+    pub double_checks: u64,
+    pub checkmates: u64,
+}
+
+impl AddAssign for PerftStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nodes += rhs.nodes;
+        self.captures += rhs.captures;
+        self.en_passants += rhs.en_passants;
+        self.castles += rhs.castles;
+        self.promotions += rhs.promotions;
+        self.checks += rhs.checks;
+        self.discovered_checks += rhs.discovered_checks;
+        self.double_checks += rhs.double_checks;
+        self.checkmates += rhs.checkmates;
+    }
+}
+
+// perft that also classifies every leaf move, much slower than perft
+pub fn perft_stats(st: &mut State, depth: usize) -> PerftStats {
+    let mut stats = PerftStats::default();
+    if depth == 0 {
+        stats.nodes = 1;
+        return stats;
+    }
+    let mut moves = MoveList::new();
+    legal_moves_into(st, &mut moves);
+    for &mv in &moves {
+        if depth == 1 {
+            stats += leaf_stats(st, mv);
+        } else {
+            st.push(mv);
+            stats += perft_stats(st, depth - 1);
+            st.pop();
+        }
+    }
+    stats
+}
+
+// perft_stats for every depth from 1 to depth in a single traversal, since a
+// move played at ply i is a leaf of the depth i + 1 tree
+pub fn perft_stats_by_depth(st: &mut State, depth: usize) -> Vec<PerftStats> {
+    let mut stats = vec![PerftStats::default(); depth];
+    collect_stats(st, &mut stats);
+    stats
+}
+
+fn collect_stats(st: &mut State, stats: &mut [PerftStats]) {
+    let Some((ply_stats, deeper)) = stats.split_first_mut() else {
+        return;
+    };
+    let mut moves = MoveList::new();
+    legal_moves_into(st, &mut moves);
+    for &mv in &moves {
+        *ply_stats += leaf_stats(st, mv);
+        if !deeper.is_empty() {
+            st.push(mv);
+            collect_stats(st, deeper);
+            st.pop();
+        }
+    }
+}
+
+fn leaf_stats(st: &mut State, mv: PackedMove) -> PerftStats {
+    let is_en_passant = mv.flag() == MoveFlag::EnPassant;
+    let is_castle = mv.is_castle();
+
+    // the piece that moved, or the rook when castling, gives a direct check,
+    // any other lone checker was uncovered by the move (double checks are
+    // counted separately, matching the usual reference tables)
+    let direct_checker = if is_castle {
+        Vector::new((mv.from().x + mv.to().x) / 2, mv.to().y)
+    } else {
+        mv.to()
+    };
+
+    let mut stats = PerftStats {
+        nodes: 1,
+        captures: mv.is_capture() as u64,
+        en_passants: is_en_passant as u64,
+        castles: is_castle as u64,
+        promotions: mv.is_promotion() as u64,
+        ..Default::default()
+    };
+
+    st.push(mv);
+    let mut checkers = checkers(st);
+    let num_checkers = checkers.num_set();
+    if num_checkers > 0 {
+        stats.checks = 1;
+        stats.double_checks = (num_checkers > 1) as u64;
+        checkers.unset(direct_checker);
+        stats.discovered_checks = (num_checkers == 1 && checkers.num_set() == 1) as u64;
+        stats.checkmates = !has_legal_move(st) as u64;
+    }
+    st.pop();
+    stats
+}
@@ -0,0 +1,258 @@
+use std::fmt::Display;
+
+use crate::{
+    board::Soldier,
+    moves::{has_legal_move, legal_moves, Move},
+    state::State,
+    vector::Vector,
+};
+
+impl Move {
+    // standard algebraic notation for this move, which must be legal in st
+    pub fn to_san(&self, st: &State) -> String {
+        let (s, _) = st.board.get(self.from).expect("no piece on from square");
+        let mut san = String::new();
+
+        if s == Soldier::King && (self.to.x - self.from.x).abs() == 2 {
+            san.push_str(if self.to.x > self.from.x {
+                "O-O"
+            } else {
+                "O-O-O"
+            });
+        } else {
+            let is_capture =
+                st.board.get(self.to).is_some() || (s == Soldier::Pawn && self.from.x != self.to.x);
+            if s == Soldier::Pawn {
+                if is_capture {
+                    san.push(file_char(self.from));
+                }
+            } else {
+                san.push(s.letter());
+                san.push_str(&self.disambiguation(st, s));
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&self.to.to_string());
+            if let Some(promotion) = self.promotion {
+                san.push('=');
+                san.push(promotion.letter());
+            }
+        }
+
+        let mut after = st.clone();
+        after.push(*self);
+        if after.is_check() {
+            san.push(if !has_legal_move(&after) { '#' } else { '+' });
+        }
+        san
+    }
+
+    // the file, rank or square of the from square, as little as is needed to tell
+    // this move apart from the other pieces of the same kind that can reach the target
+    fn disambiguation(&self, st: &State, s: Soldier) -> String {
+        let others: Vec<Vector> = legal_moves(st)
+            .iter()
+            .filter(|mv| {
+                mv.to == self.to
+                    && mv.from != self.from
+                    && st.board.get(mv.from).map(|(os, _)| os) == Some(s)
+            })
+            .map(|mv| mv.from)
+            .collect();
+
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|o| o.x != self.from.x) {
+            file_char(self.from).to_string()
+        } else if others.iter().all(|o| o.y != self.from.y) {
+            rank_char(self.from).to_string()
+        } else {
+            self.from.to_string()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    // the text is not a move
+    Invalid,
+    // no legal move matches
+    Illegal,
+    // more than one legal move matches
+    Ambiguous,
+}
+
+impl Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Invalid => write!(f, "invalid SAN"),
+            SanError::Illegal => write!(f, "illegal move"),
+            SanError::Ambiguous => write!(f, "ambiguous move"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+impl State {
+    // finds the legal move written in SAN, accepting common variants such as
+    // 0-0, e8Q, missing check marks and trailing annotations like !?
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let san = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let moves = legal_moves(self);
+
+        let castle = match san {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+        let candidates: Vec<&Move> = if let Some(x) = castle {
+            moves
+                .iter()
+                .filter(|mv| {
+                    matches!(self.board.get(mv.from), Some((Soldier::King, _)))
+                        && mv.to.x == x
+                        && (mv.to.x - mv.from.x).abs() == 2
+                })
+                .collect()
+        } else {
+            // a leading lowercase b is a file, and only read as a bishop when
+            // no pawn move fits
+            let pawn = SanPattern::parse(san, false);
+            let bishop = san
+                .starts_with('b')
+                .then(|| SanPattern::parse(san, true))
+                .flatten();
+            if pawn.is_none() && bishop.is_none() {
+                return Err(SanError::Invalid);
+            }
+            let matching = |pattern: &SanPattern| -> Vec<&Move> {
+                moves
+                    .iter()
+                    .filter(|mv| pattern.matches(self, mv))
+                    .collect()
+            };
+            let mut candidates = pawn.map_or_else(Vec::new, |p| matching(&p));
+            if let (true, Some(bishop)) = (candidates.is_empty(), bishop) {
+                candidates = matching(&bishop);
+            }
+            candidates
+        };
+
+        match candidates[..] {
+            [mv] => Ok(*mv),
+            [] => Err(SanError::Illegal),
+            _ => Err(SanError::Ambiguous),
+        }
+    }
+}
+
+// the pieces of a non-castling SAN move
+struct SanPattern {
+    soldier: Soldier,
+    from_file: Option<i8>,
+    from_rank: Option<i8>,
+    to: Vector,
+    promotion: Option<Soldier>,
+}
+
+impl SanPattern {
+    // piece letters may be in either case, with an optional P for pawns; a
+    // leading lowercase b is read as a bishop only when asked to
+    fn parse(san: &str, lowercase_bishop: bool) -> Option<Self> {
+        let mut chars: Vec<char> = san.chars().collect();
+
+        let soldier = match chars.first() {
+            Some('b') if !lowercase_bishop => None,
+            Some('P' | 'p') => Some(Soldier::Pawn),
+            Some(c) => letter_to_soldier(c.to_ascii_uppercase()),
+            None => None,
+        };
+        let soldier = match soldier {
+            Some(s) => {
+                chars.remove(0);
+                s
+            }
+            None => Soldier::Pawn,
+        };
+
+        // promotion, written as =Q or just Q (in either case) after the rank
+        let mut promotion = None;
+        if let Some(&c) = chars.last() {
+            if !c.is_ascii_digit() {
+                let p = letter_to_soldier(c.to_ascii_uppercase())?;
+                if soldier != Soldier::Pawn || matches!(p, Soldier::King) {
+                    return None;
+                }
+                promotion = Some(p);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        if chars.len() < 2 {
+            return None;
+        }
+        let to: Vector = chars[chars.len() - 2..]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()?;
+        chars.truncate(chars.len() - 2);
+        if promotion.is_some() && to.y != 0 && to.y != 7 {
+            return None;
+        }
+
+        // whatever is left is disambiguation and capture markers
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => {
+                    from_file = Some(c as i8 - 'a' as i8)
+                }
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as i8 - '1' as i8),
+                'x' | ':' => {}
+                _ => return None,
+            }
+        }
+
+        Some(SanPattern {
+            soldier,
+            from_file,
+            from_rank,
+            to,
+            promotion,
+        })
+    }
+
+    fn matches(&self, st: &State, mv: &Move) -> bool {
+        matches!(st.board.get(mv.from), Some((s, _)) if *s == self.soldier)
+            && mv.to == self.to
+            && mv.promotion == self.promotion
+            && self.from_file.is_none_or(|x| mv.from.x == x)
+            && self.from_rank.is_none_or(|y| mv.from.y == y)
+    }
+}
+
+fn letter_to_soldier(c: char) -> Option<Soldier> {
+    match c {
+        'N' => Some(Soldier::Knight),
+        'B' => Some(Soldier::Bishop),
+        'R' => Some(Soldier::Rook),
+        'Q' => Some(Soldier::Queen),
+        'K' => Some(Soldier::King),
+        _ => None,
+    }
+}
+
+fn file_char(v: Vector) -> char {
+    (b'a' + v.x as u8) as char
+}
+
+fn rank_char(v: Vector) -> char {
+    (b'1' + v.y as u8) as char
+}
@@ -0,0 +1,280 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{Color, Soldier},
+    moves::{generate_moves, legal_moves_into, GenType, Move, MoveList},
+    packed::{MoveFlag, PackedMove},
+    state::State,
+    vector::Vector,
+};
+
+pub const MATE_SCORE: i32 = 100_000;
+const MAX_DEPTH: usize = 64;
+// negamax never goes deeper than the search depth
+const MAX_PLY: usize = MAX_DEPTH + 1;
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+// progress reported after every completed iteration
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: usize,
+    // centipawns from the side to move's point of view
+    pub score: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    // full moves until mate, negative if the side to move is getting mated
+    pub fn mate_in(&self) -> Option<i32> {
+        let plies = MATE_SCORE - self.score.abs();
+        if plies > MAX_DEPTH as i32 * 2 {
+            None
+        } else if self.score > 0 {
+            Some((plies + 1) / 2)
+        } else {
+            Some(-(plies + 1) / 2)
+        }
+    }
+}
+
+// iterative deepening alpha-beta search, stops early when the limits are hit or
+// the stop flag is raised and returns the best move of the last full iteration
+pub fn search(
+    st: &mut State,
+    limits: &SearchLimits,
+    stop: &AtomicBool,
+    mut report: impl FnMut(&SearchInfo),
+) -> Option<Move> {
+    // filler for the unused pv slots
+    let null = PackedMove::new(Vector::new(0, 0), Vector::new(0, 0), MoveFlag::Quiet);
+    let mut searcher = Searcher {
+        limits,
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        aborted: false,
+        pv: [[null; MAX_PLY]; MAX_PLY],
+        pv_len: [0; MAX_PLY],
+        prev_pv: [null; MAX_PLY],
+        prev_pv_len: 0,
+    };
+    let mut moves = MoveList::new();
+    legal_moves_into(st, &mut moves);
+    let mut best = moves.first().map(|&mv| mv.into());
+    for depth in 1..=limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH) {
+        let score = searcher.negamax(st, depth, 0, -MATE_SCORE, MATE_SCORE, true);
+        if searcher.aborted {
+            break;
+        }
+        let pv = &searcher.pv[0][..searcher.pv_len[0]];
+        searcher.prev_pv[..pv.len()].copy_from_slice(pv);
+        searcher.prev_pv_len = pv.len();
+        best = pv.first().map(|&mv| mv.into()).or(best);
+        report(&SearchInfo {
+            depth,
+            score,
+            nodes: searcher.nodes,
+            elapsed: searcher.start.elapsed(),
+            pv: pv.iter().map(|&mv| mv.into()).collect(),
+        });
+        // no point searching deeper once a forced mate is found
+        if score.abs() >= MATE_SCORE - depth as i32 {
+            break;
+        }
+    }
+    best
+}
+
+// time to spend on a move: a fraction of the remaining time plus most of the
+// increment, keeping a margin so we never flag
+pub fn allocate_time(time: Duration, inc: Duration, movestogo: Option<u64>) -> Duration {
+    let margin = Duration::from_millis(50);
+    let moves = movestogo.unwrap_or(30).clamp(1, u32::MAX as u64) as u32;
+    let budget = time / moves + inc * 3 / 4;
+    budget
+        .min(time.saturating_sub(margin))
+        .max(Duration::from_millis(1))
+}
+
+struct Searcher<'a> {
+    limits: &'a SearchLimits,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    aborted: bool,
+    // triangular pv table: row ply holds the best line found from that ply,
+    // so no line is allocated during the search
+    pv: [[PackedMove; MAX_PLY]; MAX_PLY],
+    pv_len: [usize; MAX_PLY],
+    // the last iteration's pv, searched first while we are still on it
+    prev_pv: [PackedMove; MAX_PLY],
+    prev_pv_len: usize,
+}
+
+impl Searcher<'_> {
+    fn check_limits(&mut self) {
+        self.nodes += 1;
+        if !self.nodes.is_multiple_of(1024) {
+            return;
+        }
+        let out_of_time = self
+            .limits
+            .movetime
+            .is_some_and(|t| self.start.elapsed() >= t);
+        let out_of_nodes = self.limits.nodes.is_some_and(|n| self.nodes >= n);
+        if out_of_time || out_of_nodes || self.stop.load(Ordering::Relaxed) {
+            self.aborted = true;
+        }
+    }
+
+    fn negamax(
+        &mut self,
+        st: &mut State,
+        depth: usize,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        on_pv: bool,
+    ) -> i32 {
+        self.pv_len[ply] = 0;
+        self.check_limits();
+        if self.aborted {
+            return 0;
+        }
+        if ply > 0 && (st.halfmove_clock >= 100 || st.is_repetition(2)) {
+            return 0;
+        }
+
+        let mut moves = MoveList::new();
+        legal_moves_into(st, &mut moves);
+        if moves.is_empty() {
+            return if st.is_check() {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        if depth == 0 {
+            return self.quiescence(st, alpha, beta);
+        }
+
+        // principal variation move from the last iteration first, then captures
+        let pv_move = (on_pv && ply < self.prev_pv_len).then(|| self.prev_pv[ply]);
+        order_moves(st, &mut moves, pv_move);
+        for &mv in &moves {
+            // only follow the previous pv while we are still on it
+            let child_on_pv = pv_move == Some(mv);
+            st.push(mv);
+            let score = -self.negamax(st, depth - 1, ply + 1, -beta, -alpha, child_on_pv);
+            st.pop();
+            if self.aborted {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                let len = self.pv_len[ply + 1];
+                let (rows, child_rows) = self.pv.split_at_mut(ply + 1);
+                rows[ply][0] = mv;
+                rows[ply][1..=len].copy_from_slice(&child_rows[0][..len]);
+                self.pv_len[ply] = len + 1;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+
+    fn quiescence(&mut self, st: &mut State, mut alpha: i32, beta: i32) -> i32 {
+        self.check_limits();
+        if self.aborted {
+            return 0;
+        }
+        let stand_pat = evaluate(st);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut captures = MoveList::new();
+        generate_moves(st, GenType::Captures, &mut captures);
+        order_moves(st, &mut captures, None);
+        for &mv in &captures {
+            st.push(mv);
+            let score = -self.quiescence(st, -beta, -alpha);
+            st.pop();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+// most valuable victim, least valuable attacker, sorted in place so ordering
+// does not allocate
+fn order_moves(st: &State, moves: &mut [PackedMove], first: Option<PackedMove>) {
+    moves.sort_unstable_by_key(|mv| {
+        if Some(*mv) == first {
+            return i32::MIN;
+        }
+        let victim = match mv.flag() {
+            MoveFlag::EnPassant => soldier_value(Soldier::Pawn),
+            _ if mv.is_capture() => soldier_value(st.board.get(mv.to()).unwrap().0),
+            _ => 0,
+        };
+        let promotion = mv.promotion().map_or(0, soldier_value);
+        let (attacker, _) = st.board.get(mv.from()).unwrap();
+        if victim + promotion > 0 {
+            -(10 * (victim + promotion) - soldier_value(attacker))
+        } else {
+            0
+        }
+    });
+}
+
+fn soldier_value(s: Soldier) -> i32 {
+    match s {
+        Soldier::Pawn => 100,
+        Soldier::Knight => 320,
+        Soldier::Bishop => 330,
+        Soldier::Rook => 500,
+        Soldier::Queen => 900,
+        Soldier::King => 0,
+    }
+}
+
+// material plus a small bonus for central minor pieces and advanced pawns,
+// from the side to move's point of view
+pub fn evaluate(st: &State) -> i32 {
+    let mut score = 0;
+    for pos in st.board.occupancy().iter() {
+        if let Some((s, c)) = st.board.get(pos) {
+            let center_distance = (2 * pos.x - 7).abs().max((2 * pos.y - 7).abs()) as i32;
+            let bonus = match s {
+                Soldier::Knight | Soldier::Bishop => 4 * (7 - center_distance),
+                Soldier::Pawn if *c == Color::White => 5 * (pos.y as i32 - 1),
+                Soldier::Pawn => 5 * (6 - pos.y as i32),
+                _ => 0,
+            };
+            let value = soldier_value(*s) + bonus;
+            score += if *c == st.turn { value } else { -value };
+        }
+    }
+    score
+}
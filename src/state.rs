@@ -1,11 +1,11 @@
 use crate::{
     board::{Board, Color, Piece, Soldier},
-    moves::Move,
+    packed::{IntoPacked, MoveFlag, PackedMove},
     vector::Vector,
+    zobrist::{piece_key, turn_key},
 };
 
-const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-
+#[derive(Clone)]
 pub struct State {
     pub board: Board,
     pub turn: Color,
@@ -14,23 +14,51 @@ pub struct State {
     pub black_castle_kingside: bool,
     pub black_castle_queenside: bool,
     pub en_passant_square: Option<Vector>,
+    // plies since the last capture or pawn move, for the fifty-move rule
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    // position key, updated incrementally as moves are pushed and popped
+    pub zobrist: u64,
     // stack that defines how to return to the previous state
     pub reversions: Vec<Reversion>,
 }
 
+#[derive(Clone)]
 pub struct Reversion {
-    mv: Move,
+    mv: PackedMove,
     captured_piece: Option<Piece>,
     white_castle_kingside: bool,
     white_castle_queenside: bool,
     black_castle_kingside: bool,
     black_castle_queenside: bool,
     en_passant_square: Option<Vector>,
+    halfmove_clock: u32,
+    zobrist: u64,
+}
+
+impl Reversion {
+    fn castling_rights(&self) -> [bool; 4] {
+        [
+            self.white_castle_kingside,
+            self.white_castle_queenside,
+            self.black_castle_kingside,
+            self.black_castle_queenside,
+        ]
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
+    pub const STARTING_FEN: &'static str =
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
     pub fn new() -> Self {
-        Self::from_fen(STARTING_FEN)
+        Self::from_fen(Self::STARTING_FEN)
     }
 
     pub fn castling_rights_for_color(&self) -> (bool, bool) {
@@ -40,16 +68,70 @@ impl State {
         }
     }
 
-    pub fn push(&mut self, mv: Move) {
-        // remove piece from old square and move to new one
-        let (mut s, c) = self.board.remove(mv.from).unwrap();
-        if let Some(promotion_piece) = mv.promotion {
-            s = promotion_piece;
+    pub(crate) fn castling_rights(&self) -> [bool; 4] {
+        [
+            self.white_castle_kingside,
+            self.white_castle_queenside,
+            self.black_castle_kingside,
+            self.black_castle_queenside,
+        ]
+    }
+
+    // number of times the current position has occurred in the game, only
+    // searching back to the last irreversible move since nothing before it can repeat
+    pub fn repetition_count(&self) -> usize {
+        let mut count = 1;
+        let mut next = (self.halfmove_clock, self.castling_rights());
+        for (i, reversion) in self.reversions.iter().enumerate().rev() {
+            let rights = reversion.castling_rights();
+            if next.0 == 0 || next.1 != rights {
+                break;
+            }
+            // only positions with the same side to move can be equal
+            if (self.reversions.len() - i).is_multiple_of(2) && reversion.zobrist == self.zobrist {
+                count += 1;
+            }
+            next = (reversion.halfmove_clock, rights);
+        }
+        count
+    }
+
+    pub fn is_repetition(&self, n: usize) -> bool {
+        self.repetition_count() >= n
+    }
+
+    // the flag says which special rules apply, so a packed move is played
+    // without re-deriving anything from the board
+    pub fn push(&mut self, mv: impl IntoPacked) {
+        let mv = mv.into_packed(self);
+        let (from, to, flag) = (mv.from(), mv.to(), mv.flag());
+
+        // hash out the rights that may change, they are hashed back in at the end
+        let mut zobrist = self.zobrist ^ self.castling_key() ^ self.en_passant_key();
+
+        // an en passant capture takes the pawn beside the one moving, not on its target
+        let captured_pos = if flag == MoveFlag::EnPassant {
+            Vector::new(to.x, from.y)
+        } else {
+            to
+        };
+        let captured_piece = if mv.is_capture() {
+            self.board.remove(captured_pos)
+        } else {
+            None
+        };
+        if let Some(p) = captured_piece {
+            zobrist ^= piece_key(p, captured_pos);
         }
-        let captured_piece = self.board.set(mv.to, Some((s, c)));
+
+        // remove piece from old square and move to new one
+        let (moved, c) = self.board.remove(from).unwrap();
+        let s = mv.promotion().unwrap_or(moved);
+        self.board.set(to, Some((s, c)));
+        zobrist ^= piece_key((moved, c), from) ^ piece_key((s, c), to);
 
         // save how to revert this move
-        let mut reversion = Reversion {
+        let reversion = Reversion {
             mv,
             captured_piece,
             white_castle_kingside: self.white_castle_kingside,
@@ -57,10 +139,26 @@ impl State {
             black_castle_kingside: self.black_castle_kingside,
             black_castle_queenside: self.black_castle_queenside,
             en_passant_square: self.en_passant_square,
+            halfmove_clock: self.halfmove_clock,
+            zobrist: self.zobrist,
+        };
+
+        // move the rook when castling
+        let rook_move = match flag {
+            MoveFlag::KingCastle => Some((7, 5)),
+            MoveFlag::QueenCastle => Some((0, 3)),
+            _ => None,
         };
+        if let Some((rook_from, rook_to)) = rook_move {
+            let rook = (Soldier::Rook, self.turn);
+            self.board.remove(Vector::new(rook_from, from.y));
+            self.board.set(Vector::new(rook_to, from.y), Some(rook));
+            zobrist ^= piece_key(rook, Vector::new(rook_from, from.y))
+                ^ piece_key(rook, Vector::new(rook_to, from.y));
+        }
 
-        // update king moving castling rights and perform castling
-        if s == Soldier::King {
+        // update king moving castling rights
+        if moved == Soldier::King {
             if self.turn == Color::White {
                 self.white_castle_kingside = false;
                 self.white_castle_queenside = false;
@@ -68,103 +166,91 @@ impl State {
                 self.black_castle_kingside = false;
                 self.black_castle_queenside = false;
             }
-            match mv.to.x - mv.from.x {
-                2 => {
-                    self.board.remove(Vector::new(7, mv.from.y));
-                    self.board
-                        .set(Vector::new(5, mv.from.y), Some((Soldier::Rook, self.turn)));
-                }
-                -2 => {
-                    self.board.remove(Vector::new(0, mv.from.y));
-                    self.board
-                        .set(Vector::new(3, mv.from.y), Some((Soldier::Rook, self.turn)));
-                }
-                _ => {}
-            }
         }
 
         // check for rooks moved/captured to update castling rights
-        if mv.from == Vector::new(7, 0) || mv.to == Vector::new(7, 0) {
+        if from == Vector::new(7, 0) || to == Vector::new(7, 0) {
             self.white_castle_kingside = false;
         }
-        if mv.from == Vector::new(0, 0) || mv.to == Vector::new(0, 0) {
+        if from == Vector::new(0, 0) || to == Vector::new(0, 0) {
             self.white_castle_queenside = false;
         }
-        if mv.from == Vector::new(7, 7) || mv.to == Vector::new(7, 7) {
+        if from == Vector::new(7, 7) || to == Vector::new(7, 7) {
             self.black_castle_kingside = false;
         }
-        if mv.from == Vector::new(0, 7) || mv.to == Vector::new(0, 7) {
+        if from == Vector::new(0, 7) || to == Vector::new(0, 7) {
             self.black_castle_queenside = false;
         }
 
-        // check if we took enpassant and remove the captured pawn if so
-        if let Some(ev) = self.en_passant_square {
-            let dy = if self.turn == Color::White { -1 } else { 1 };
-            if s == Soldier::Pawn && mv.to == ev {
-                reversion.captured_piece = self.board.remove(Vector::new(ev.x, ev.y + dy));
-            }
-            self.en_passant_square = None;
-        }
+        // a double push leaves the skipped square open to en passant
+        self.en_passant_square = if flag == MoveFlag::DoublePush {
+            Some(Vector::new(from.x, (from.y + to.y) / 2))
+        } else {
+            None
+        };
 
-        // update enpassant square if pawn moved 2 squares
-        if s == Soldier::Pawn && (mv.from.y - mv.to.y).abs() == 2 {
-            let dy = if self.turn == Color::White { -1 } else { 1 };
-            self.en_passant_square = Some(Vector::new(mv.from.x, mv.to.y + dy));
+        // pawn moves and captures are irreversible, so they reset the clock
+        if moved == Soldier::Pawn || captured_piece.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.turn == Color::Black {
+            self.fullmove_number += 1;
         }
 
         // their turn now!
         self.turn = self.turn.opposite();
 
+        self.zobrist = zobrist ^ turn_key() ^ self.castling_key() ^ self.en_passant_key();
+        debug_assert_eq!(self.zobrist, self.compute_zobrist(), "zobrist out of sync");
+
         self.reversions.push(reversion);
     }
 
     pub fn pop(&mut self) {
         assert!(!self.reversions.is_empty(), "pop from root state");
         let reversion = self.reversions.pop().unwrap();
+        let (mv, from, to) = (reversion.mv, reversion.mv.from(), reversion.mv.to());
 
         self.white_castle_kingside = reversion.white_castle_kingside;
         self.white_castle_queenside = reversion.white_castle_queenside;
         self.black_castle_kingside = reversion.black_castle_kingside;
         self.black_castle_queenside = reversion.black_castle_queenside;
         self.en_passant_square = reversion.en_passant_square;
+        self.halfmove_clock = reversion.halfmove_clock;
+        self.zobrist = reversion.zobrist;
         self.turn = self.turn.opposite();
+        if self.turn == Color::Black {
+            self.fullmove_number -= 1;
+        }
 
-        let (mut s, c) = self.board.remove(reversion.mv.to).unwrap();
-        if reversion.mv.promotion.is_some() {
+        let (mut s, c) = self.board.remove(to).unwrap();
+        if mv.is_promotion() {
             s = Soldier::Pawn;
         }
-        self.board.set(reversion.mv.from, Some((s, c)));
+        self.board.set(from, Some((s, c)));
 
-        if let Some(ev) = reversion.en_passant_square {
-            let dy = if self.turn == Color::White { -1 } else { 1 };
-            if s == Soldier::Pawn && reversion.mv.to == ev {
+        match mv.flag() {
+            MoveFlag::EnPassant => {
                 self.board
-                    .set(Vector::new(ev.x, ev.y + dy), reversion.captured_piece);
-            } else {
-                self.board.set(reversion.mv.to, reversion.captured_piece);
+                    .set(Vector::new(to.x, from.y), reversion.captured_piece);
             }
-        } else {
-            self.board.set(reversion.mv.to, reversion.captured_piece);
-        }
-
-        if s == Soldier::King {
-            match reversion.mv.to.x - reversion.mv.from.x {
-                2 => {
-                    self.board.remove(Vector::new(5, reversion.mv.from.y));
-                    self.board.set(
-                        Vector::new(7, reversion.mv.from.y),
-                        Some((Soldier::Rook, c)),
-                    );
-                }
-                -2 => {
-                    self.board.remove(Vector::new(3, reversion.mv.from.y));
-                    self.board.set(
-                        Vector::new(0, reversion.mv.from.y),
-                        Some((Soldier::Rook, c)),
-                    );
-                }
-                _ => {}
+            MoveFlag::KingCastle => {
+                self.board.remove(Vector::new(5, from.y));
+                self.board
+                    .set(Vector::new(7, from.y), Some((Soldier::Rook, c)));
+            }
+            MoveFlag::QueenCastle => {
+                self.board.remove(Vector::new(3, from.y));
+                self.board
+                    .set(Vector::new(0, from.y), Some((Soldier::Rook, c)));
+            }
+            _ => {
+                self.board.set(to, reversion.captured_piece);
             }
         }
+
+        debug_assert_eq!(self.zobrist, self.compute_zobrist(), "zobrist out of sync");
     }
 }
@@ -1,14 +1,37 @@
 #[cfg(test)]
 mod tests {
-    use serde::Deserialize;
+    use serde::{de::DeserializeOwned, Deserialize};
 
     use crate::{
-        board::Soldier,
-        moves::{legal_moves, Move},
+        bitboard::{king_attacks, knight_attacks, pawn_attacks, BitBoard},
+        board::{Color, Soldier},
+        fen::{FenError, FenField},
+        magic::{bishop_attacks, queen_attacks, rook_attacks},
+        moves::{
+            generate_moves, has_legal_move, legal_moves, legal_moves_into, num_legal_moves,
+            GenType, LegalMoves, Move, MoveList,
+        },
+        outcome::Outcome,
+        packed::{MoveFlag, PackedMove},
+        perft::{
+            divide, divide_parallel, perft, perft_hashed, perft_parallel, perft_stats,
+            perft_stats_by_depth, PerftStats, PerftTable,
+        },
+        san::SanError,
+        search::{allocate_time, search, SearchLimits},
         state::State,
+        uci::UciError,
         vector::Vector,
     };
-    use std::fs;
+    use std::{
+        fs,
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    fn load<T: DeserializeOwned>(path: &str) -> Vec<T> {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
 
     #[derive(Deserialize)]
     struct JsonMove {
@@ -25,8 +48,7 @@ mod tests {
 
     #[test]
     fn legal_moves_test() {
-        let positions: Vec<Position> =
-            serde_json::from_str(&fs::read_to_string("test-data/positions.json").unwrap()).unwrap();
+        let positions: Vec<Position> = load("test-data/positions.json");
 
         for position in positions {
             let mut expected_moves: Vec<Move> = position
@@ -59,35 +81,1087 @@ mod tests {
         }
     }
 
+    #[test]
+    fn move_list_capacity_test() {
+        // a position with the most moves a real game can reach
+        let st = State::from_fen("R6R/3Q4/1Q4Q1/4Q3/2Q4Q/Q4Q2/pp1Q4/kBNN1KB1 w - - 0 1");
+        let mut list = MoveList::new();
+        legal_moves_into(&st, &mut list);
+        assert_eq!(list.len(), 218);
+
+        // more moves than a list can hold, rejected before any are generated
+        let fen = "QQQQ1Qbk/Q4Qpp/Q5QQ/4Q3/Q6Q/Q6Q/Q6Q/KQQQQQQQ w - - 0 1";
+        assert!(State::try_from_fen(fen).is_err());
+    }
+
+    #[test]
+    fn legal_moves_iter_test() {
+        let positions: Vec<Position> = load("test-data/positions.json");
+
+        for position in positions {
+            let st = State::from_fen(&position.fen);
+            let legal_moves = legal_moves(&st);
+            // the lazy iterator yields the same moves in the same order
+            assert_eq!(
+                LegalMoves::new(&st).map(Move::from).collect::<Vec<_>>(),
+                legal_moves,
+                "FEN: {}",
+                position.fen
+            );
+            assert_eq!(
+                has_legal_move(&st),
+                !legal_moves.is_empty(),
+                "FEN: {}",
+                position.fen
+            );
+        }
+    }
+
+    #[test]
+    fn legal_moves_into_test() {
+        let positions: Vec<Position> = load("test-data/positions.json");
+
+        // one list reused for every position, each fill must replace the last
+        let mut list = MoveList::new();
+        for position in positions {
+            let st = State::from_fen(&position.fen);
+            legal_moves_into(&st, &mut list);
+            let mut list_moves: Vec<Move> = list.iter().map(|&mv| mv.into()).collect();
+            let mut legal_moves = legal_moves(&st);
+            list_moves.sort();
+            legal_moves.sort();
+            assert_eq!(list_moves, legal_moves, "FEN: {}", position.fen);
+        }
+    }
+
+    // checks every generation mode against the full legal move list
+    fn assert_gen_types(st: &mut State) {
+        let sorted = |st: &State, gen| {
+            let mut list = MoveList::new();
+            generate_moves(st, gen, &mut list);
+            // the generator's flags agree with packing the move afterwards
+            let mut moves: Vec<Move> = list
+                .iter()
+                .map(|&packed| {
+                    let mv = Move::from(packed);
+                    assert_eq!(st.pack(mv), packed, "FEN: {}", st.to_fen());
+                    mv
+                })
+                .collect();
+            moves.sort();
+            moves
+        };
+        let all = sorted(st, GenType::All);
+        let captures = sorted(st, GenType::Captures);
+        let quiets = sorted(st, GenType::Quiets);
+        let fen = st.to_fen();
+
+        let mut staged = [captures.clone(), quiets.clone()].concat();
+        staged.sort();
+        assert_eq!(staged, all, "FEN: {}", fen);
+        for mv in &captures {
+            let packed = st.pack(*mv);
+            assert!(packed.is_capture() || packed.is_promotion(), "FEN: {}", fen);
+        }
+
+        let evasions = if st.is_check() { all.clone() } else { vec![] };
+        assert_eq!(sorted(st, GenType::Evasions), evasions, "FEN: {}", fen);
+
+        let quiet_checks: Vec<Move> = quiets
+            .into_iter()
+            .filter(|&mv| {
+                st.push(mv);
+                let check = st.is_check();
+                st.pop();
+                check
+            })
+            .collect();
+        assert_eq!(
+            sorted(st, GenType::QuietChecks),
+            quiet_checks,
+            "FEN: {}",
+            fen
+        );
+    }
+
+    #[test]
+    fn gen_types_test() {
+        let fens = [
+            State::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+        for fen in fens {
+            let mut st = State::from_fen(fen);
+            assert_gen_types(&mut st);
+            for mv in legal_moves(&st) {
+                st.push(mv);
+                assert_gen_types(&mut st);
+                for reply in legal_moves(&st) {
+                    st.push(reply);
+                    assert_gen_types(&mut st);
+                    st.pop();
+                }
+                st.pop();
+            }
+        }
+    }
+
+    // the move type breakdown is optional, and only checked when present
     #[derive(Deserialize)]
     struct PerftPosition {
         depth: usize,
-        nodes: usize,
+        nodes: u64,
+        captures: Option<u64>,
+        en_passants: Option<u64>,
+        castles: Option<u64>,
+        promotions: Option<u64>,
+        checks: Option<u64>,
+        discovered_checks: Option<u64>,
+        double_checks: Option<u64>,
+        checkmates: Option<u64>,
         fen: String,
     }
 
-    fn count_nodes(st: &mut State, depth: usize) -> usize {
-        if depth == 0 {
-            return 1;
+    #[test]
+    fn perft_tests() {
+        let positions: Vec<PerftPosition> = load("test-data/perft.json");
+
+        for position in positions {
+            let mut st = State::from_fen(&position.fen);
+            let num_nodes = perft(&mut st, position.depth);
+            assert_eq!(num_nodes, position.nodes, "FEN: {}", position.fen);
+        }
+    }
+
+    #[test]
+    fn perft_hashed_tests() {
+        let positions: Vec<PerftPosition> = load("test-data/perft.json");
+
+        // a small table so that slots get overwritten
+        let mut table = PerftTable::new(1 << 12);
+        for position in positions {
+            let mut st = State::from_fen(&position.fen);
+            assert_eq!(num_legal_moves(&st), legal_moves(&st).len());
+            for depth in 1..=position.depth {
+                assert_eq!(
+                    perft_hashed(&mut st, depth, &mut table),
+                    perft(&mut st, depth),
+                    "FEN: {}",
+                    position.fen
+                );
+            }
+            assert_eq!(
+                perft_hashed(&mut st, position.depth, &mut table),
+                position.nodes
+            );
+        }
+    }
+
+    // the deep run the table is for, too slow for a debug build
+    #[test]
+    #[ignore]
+    fn perft_hashed_kiwipete_test() {
+        let mut st =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mut table = PerftTable::with_megabytes(256);
+        let start = Instant::now();
+        assert_eq!(perft_hashed(&mut st, 6, &mut table), 8_031_647_685);
+        assert!(
+            start.elapsed() < Duration::from_secs(60),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[test]
+    fn perft_parallel_tests() {
+        let positions: Vec<PerftPosition> = load("test-data/perft.json");
+
+        for position in positions {
+            let st = State::from_fen(&position.fen);
+            let nodes = perft_parallel(&st, position.depth, 4);
+            assert_eq!(nodes, position.nodes, "FEN: {}", position.fen);
+        }
+
+        let mut st = State::new();
+        assert_eq!(divide_parallel(&st, 3, 4), divide(&mut st, 3));
+    }
+
+    #[test]
+    fn perft_stats_tests() {
+        let positions: Vec<PerftPosition> = load("test-data/perft.json");
+
+        for position in positions.iter().filter(|p| p.captures.is_some()) {
+            let stats = perft_stats(&mut State::from_fen(&position.fen), position.depth);
+            let expected = PerftStats {
+                nodes: position.nodes,
+                captures: position.captures.unwrap(),
+                en_passants: position.en_passants.unwrap(),
+                castles: position.castles.unwrap(),
+                promotions: position.promotions.unwrap(),
+                checks: position.checks.unwrap(),
+                discovered_checks: position.discovered_checks.unwrap(),
+                double_checks: position.double_checks.unwrap(),
+                checkmates: position.checkmates.unwrap(),
+            };
+            assert_eq!(stats, expected, "FEN: {}", position.fen);
+        }
+    }
+
+    #[test]
+    fn perft_stats_by_depth_test() {
+        let fens = [
+            State::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            let mut st = State::from_fen(fen);
+            let by_depth = perft_stats_by_depth(&mut st, 3);
+            assert_eq!(by_depth.len(), 3);
+            for (d, stats) in by_depth.iter().enumerate() {
+                assert_eq!(*stats, perft_stats(&mut st, d + 1), "FEN: {}", fen);
+            }
         }
-        let mut num_nodes = 0;
-        for mv in legal_moves(st) {
+        assert!(perft_stats_by_depth(&mut State::new(), 0).is_empty());
+    }
+
+    #[test]
+    fn fen_round_trip_test() {
+        let positions: Vec<Position> = load("test-data/positions.json");
+        let perft_positions: Vec<PerftPosition> = load("test-data/perft.json");
+
+        let fens = positions
+            .iter()
+            .map(|p| &p.fen)
+            .chain(perft_positions.iter().map(|p| &p.fen));
+        for fen in fens {
+            // clocks default to 0 1 when omitted
+            let fields: Vec<&str> = fen.split(' ').collect();
+            let expected = if fields.len() == 4 {
+                format!("{} 0 1", fen)
+            } else {
+                fen.clone()
+            };
+            let st = State::from_fen(fen);
+            assert_eq!(st.to_fen(), expected);
+            assert_eq!(State::from_fen(&st.to_fen()).to_fen(), st.to_fen());
+        }
+    }
+
+    #[test]
+    fn clocks_test() {
+        let mut st = State::new();
+        let moves = [
+            (
+                (6, 0, 5, 2),
+                "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1",
+            ),
+            (
+                (4, 6, 4, 4),
+                "rnbqkbnr/pppp1ppp/8/4p3/8/5N2/PPPPPPPP/RNBQKB1R w KQkq e6 0 2",
+            ),
+            (
+                (5, 2, 4, 4),
+                "rnbqkbnr/pppp1ppp/8/4N3/8/8/PPPPPPPP/RNBQKB1R b KQkq - 0 2",
+            ),
+            (
+                (1, 7, 2, 5),
+                "r1bqkbnr/pppp1ppp/2n5/4N3/8/8/PPPPPPPP/RNBQKB1R w KQkq - 1 3",
+            ),
+        ];
+        for (mv, fen) in moves {
+            push_all(&mut st, &[mv]);
+            assert_eq!(st.to_fen(), fen);
+        }
+        for (_, fen) in moves.iter().rev() {
+            assert_eq!(st.to_fen(), *fen);
+            st.pop();
+        }
+        assert_eq!(st.to_fen(), State::new().to_fen());
+    }
+
+    #[test]
+    fn outcome_test() {
+        let cases = [
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                Some(Outcome::Checkmate {
+                    winner: Color::Black,
+                }),
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Some(Outcome::Stalemate)),
+            (
+                "8/8/4k3/8/8/2KB4/8/8 w - - 0 1",
+                Some(Outcome::InsufficientMaterial),
+            ),
+            (
+                "8/8/4k3/8/4b3/2KB4/8/8 w - - 0 1",
+                Some(Outcome::InsufficientMaterial),
+            ),
+            ("8/8/4k3/4b3/8/2KB4/8/8 w - - 0 1", None),
+            ("8/8/4k3/8/8/2KN4/7p/8 w - - 0 1", None),
+            (
+                "8/8/4k3/8/8/2KR4/8/8 w - - 100 80",
+                Some(Outcome::FiftyMoveRule),
+            ),
+            (
+                "8/8/4k3/8/8/2KR4/8/8 w - - 150 80",
+                Some(Outcome::SeventyFiveMoveRule),
+            ),
+        ];
+        for (fen, expected) in cases {
+            let st = State::from_fen(fen);
+            assert_eq!(st.outcome(), expected, "FEN: {}", fen);
+        }
+
+        let mut st = State::new();
+        assert!(!st.is_check());
+        let shuffle = [(6, 0, 5, 2), (6, 7, 5, 5), (5, 2, 6, 0), (5, 5, 6, 7)];
+        let mut outcomes = Vec::new();
+        for _ in 0..4 {
+            push_all(&mut st, &shuffle);
+            outcomes.push(st.outcome());
+        }
+        assert_eq!(
+            outcomes,
+            vec![
+                None,
+                Some(Outcome::ThreefoldRepetition),
+                Some(Outcome::ThreefoldRepetition),
+                Some(Outcome::FivefoldRepetition)
+            ]
+        );
+    }
+
+    fn push_all(st: &mut State, moves: &[(i8, i8, i8, i8)]) {
+        for (fx, fy, tx, ty) in moves {
+            st.push(Move {
+                from: Vector::new(*fx, *fy),
+                to: Vector::new(*tx, *ty),
+                promotion: None,
+            });
+        }
+    }
+
+    #[test]
+    fn zobrist_test() {
+        // the same position reached through different move orders
+        let mut st1 = State::new();
+        push_all(&mut st1, &[(6, 0, 5, 2), (6, 7, 5, 5), (1, 0, 2, 2)]);
+        let mut st2 = State::new();
+        push_all(&mut st2, &[(1, 0, 2, 2), (6, 7, 5, 5), (6, 0, 5, 2)]);
+        assert_eq!(st1.zobrist, st2.zobrist);
+        assert_eq!(st1.zobrist, State::from_fen(&st1.to_fen()).zobrist);
+
+        // side to move, castling rights and capturable en passant squares are hashed
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Kkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+        ];
+        for (i, f1) in fens.iter().enumerate() {
+            for f2 in &fens[i + 1..] {
+                assert_ne!(State::from_fen(f1).zobrist, State::from_fen(f2).zobrist);
+            }
+        }
+
+        // en passant squares no pawn can capture on are ignored
+        assert_eq!(
+            State::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 1")
+                .zobrist,
+            State::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").zobrist
+        );
+
+        push_all(&mut st1, &[(4, 6, 4, 4)]);
+        st1.pop();
+        assert_eq!(st1.zobrist, st2.zobrist);
+    }
+
+    #[test]
+    fn polyglot_key_test() {
+        assert_eq!(State::new().zobrist, 0x463b96181691fc9c);
+
+        // the published Polyglot examples, each key reached by playing the moves
+        let cases = [
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+        ];
+        for (moves, key) in cases {
+            let mut st = State::new();
+            for uci in moves.split(' ') {
+                st.push(st.parse_uci(uci).unwrap());
+            }
+            assert_eq!(st.zobrist, key, "moves: {}", moves);
+            assert_eq!(
+                State::from_fen(&st.to_fen()).zobrist,
+                key,
+                "moves: {}",
+                moves
+            );
+        }
+    }
+
+    // every piece bitboard and king square agrees with the mailbox
+    fn assert_board_in_sync(st: &State) {
+        for color in [Color::White, Color::Black] {
+            for s in [
+                Soldier::Pawn,
+                Soldier::Knight,
+                Soldier::Bishop,
+                Soldier::Rook,
+                Soldier::Queen,
+                Soldier::King,
+            ] {
+                for i in 0..64 {
+                    let pos = Vector::from_num(i);
+                    assert_eq!(
+                        st.board.pieces((s, color)).get(pos),
+                        *st.board.get(pos) == Some((s, color))
+                    );
+                }
+            }
+            let king = st.board.pieces((Soldier::King, color)).iter().next();
+            assert_eq!(st.board.king_pos(color), king);
+        }
+    }
+
+    #[test]
+    fn board_bitboards_test() {
+        // castling, en passant and captures are all reachable within two plies
+        let mut st =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        for mv in legal_moves(&st) {
             st.push(mv);
-            num_nodes += count_nodes(st, depth - 1);
+            assert_board_in_sync(&st);
+            for reply in legal_moves(&st) {
+                st.push(reply);
+                assert_board_in_sync(&st);
+                st.pop();
+            }
             st.pop();
         }
-        num_nodes
+        assert_board_in_sync(&st);
+        assert_eq!(st.board.count((Soldier::Pawn, Color::White)), 8);
+        assert_eq!(st.board.occupancy().num_set(), 32);
     }
 
     #[test]
-    fn perft_tests() {
-        let positions: Vec<PerftPosition> =
-            serde_json::from_str(&fs::read_to_string("test-data/perft.json").unwrap()).unwrap();
+    fn repetition_test() {
+        let shuffle = [(6, 0, 5, 2), (6, 7, 5, 5), (5, 2, 6, 0), (5, 5, 6, 7)];
+        let mut st = State::new();
+        assert_eq!(st.repetition_count(), 1);
+        push_all(&mut st, &shuffle);
+        assert_eq!(st.repetition_count(), 2);
+        push_all(&mut st, &shuffle[..2]);
+        assert_eq!(st.repetition_count(), 2);
+        push_all(&mut st, &shuffle[2..]);
+        assert_eq!(st.repetition_count(), 3);
+        assert!(st.is_repetition(3));
+        assert!(!st.is_repetition(4));
+
+        // a pawn move can never be undone, so earlier positions no longer count
+        push_all(&mut st, &[(4, 1, 4, 3), (4, 6, 4, 4)]);
+        push_all(&mut st, &shuffle);
+        assert_eq!(st.repetition_count(), 2);
+        st.pop();
+        assert_eq!(st.repetition_count(), 1);
+    }
+
+    #[test]
+    fn san_test() {
+        let cases = [
+            (State::STARTING_FEN, (6, 0, 5, 2), None, "Nf3"),
+            (State::STARTING_FEN, (4, 1, 4, 3), None, "e4"),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                (4, 0, 6, 0),
+                None,
+                "O-O",
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                (4, 0, 2, 0),
+                None,
+                "O-O-O",
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                (0, 0, 0, 7),
+                None,
+                "Rxa8+",
+            ),
+            (
+                "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+                (0, 0, 1, 1),
+                None,
+                "Qa1b2",
+            ),
+            (
+                "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+                (0, 2, 1, 1),
+                None,
+                "Q3b2",
+            ),
+            (
+                "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+                (2, 0, 1, 1),
+                None,
+                "Qcb2",
+            ),
+            (
+                "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                (4, 4, 3, 5),
+                None,
+                "exd6",
+            ),
+            (
+                "k7/4P3/8/8/8/8/8/4K3 w - - 0 1",
+                (4, 6, 4, 7),
+                Some(Soldier::Queen),
+                "e8=Q+",
+            ),
+            (
+                "k7/4P3/8/8/8/8/8/4K3 w - - 0 1",
+                (4, 6, 4, 7),
+                Some(Soldier::Knight),
+                "e8=N",
+            ),
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+                (3, 7, 7, 3),
+                None,
+                "Qh4#",
+            ),
+        ];
+        for (fen, (fx, fy, tx, ty), promotion, expected) in cases {
+            let st = State::from_fen(fen);
+            let mv = Move {
+                from: Vector::new(fx, fy),
+                to: Vector::new(tx, ty),
+                promotion,
+            };
+            assert_eq!(mv.to_san(&st), expected, "FEN: {}", fen);
+        }
+    }
 
+    #[test]
+    fn parse_san_test() {
+        let positions: Vec<Position> = load("test-data/positions.json");
         for position in positions {
-            let mut st = State::from_fen(&position.fen);
-            let num_nodes = count_nodes(&mut st, position.depth);
-            assert_eq!(num_nodes, position.nodes, "FEN: {}", position.fen);
+            let st = State::from_fen(&position.fen);
+            for mv in legal_moves(&st) {
+                assert_eq!(
+                    st.parse_san(&mv.to_san(&st)),
+                    Ok(mv),
+                    "FEN: {}",
+                    position.fen
+                );
+            }
+        }
+
+        let st = State::from_fen("r3k2r/1P6/8/3pP3/8/8/8/RN2K2R w KQkq d6 0 1");
+        let cases = [
+            ("0-0", Ok((4, 0, 6, 0, None))),
+            ("O-O-O!?", Err(SanError::Illegal)),
+            ("exd6", Ok((4, 4, 3, 5, None))),
+            ("b8Q", Ok((1, 6, 1, 7, Some(Soldier::Queen)))),
+            ("bxa8=N+", Ok((1, 6, 0, 7, Some(Soldier::Knight)))),
+            ("b8", Err(SanError::Illegal)),
+            ("R1h2", Ok((7, 0, 7, 1, None))),
+            ("Nd2", Ok((1, 0, 3, 1, None))),
+            ("Rd1", Err(SanError::Illegal)),
+            ("Zz9", Err(SanError::Invalid)),
+            ("", Err(SanError::Invalid)),
+        ];
+        for (san, expected) in cases {
+            let expected = expected.map(|(fx, fy, tx, ty, promotion)| Move {
+                from: Vector::new(fx, fy),
+                to: Vector::new(tx, ty),
+                promotion,
+            });
+            assert_eq!(st.parse_san(san), expected, "SAN: {}", san);
+        }
+
+        let st = State::new();
+        let cases = [
+            ("Pe4", Ok((4, 1, 4, 3, None))),
+            ("pe4", Ok((4, 1, 4, 3, None))),
+            ("nf3", Ok((6, 0, 5, 2, None))),
+            ("e4=K", Err(SanError::Invalid)),
+            ("e4=Q", Err(SanError::Invalid)),
+            ("Nf3=Q", Err(SanError::Invalid)),
+            ("Ke2", Err(SanError::Illegal)),
+        ];
+        for (san, expected) in cases {
+            let expected = expected.map(|(fx, fy, tx, ty, promotion)| Move {
+                from: Vector::new(fx, fy),
+                to: Vector::new(tx, ty),
+                promotion,
+            });
+            assert_eq!(st.parse_san(san), expected, "SAN: {}", san);
+        }
+
+        // lowercase b is the b-file unless only a bishop move fits
+        let st = State::from_fen("4k3/8/8/8/8/2p5/1P1B4/4K3 w - - 0 1");
+        for (san, uci) in [
+            ("bxc3", "b2c3"),
+            ("bb4", "b2b4"),
+            ("bc1", "d2c1"),
+            ("be3", "d2e3"),
+            ("kd1", "e1d1"),
+        ] {
+            assert_eq!(
+                st.parse_san(san).ok(),
+                st.parse_uci(uci).ok(),
+                "SAN: {}",
+                san
+            );
+        }
+        assert_eq!(st.parse_san("qd2"), Err(SanError::Illegal));
+
+        let st = State::from_fen("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1");
+        assert_eq!(st.parse_san("Qb2"), Err(SanError::Ambiguous));
+        assert_eq!(st.parse_san("Qab2"), Err(SanError::Ambiguous));
+    }
+
+    #[test]
+    fn uci_test() {
+        assert_eq!("a1".parse(), Ok(Vector::new(0, 0)));
+        assert_eq!("h8".parse(), Ok(Vector::new(7, 7)));
+        assert!("i1".parse::<Vector>().is_err());
+        assert!("a9".parse::<Vector>().is_err());
+        assert!("a10".parse::<Vector>().is_err());
+        for pos in Vector::board_pos_iter() {
+            assert_eq!(pos.to_string().parse(), Ok(pos));
+        }
+
+        let positions: Vec<Position> = load("test-data/positions.json");
+        for position in positions {
+            let st = State::from_fen(&position.fen);
+            for mv in legal_moves(&st) {
+                assert_eq!(st.parse_uci(&mv.to_uci()), Ok(mv), "FEN: {}", position.fen);
+            }
+        }
+
+        let st = State::from_fen("k7/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            st.parse_uci("e7e8q").map(|mv| mv.to_uci()),
+            Ok("e7e8q".to_string())
+        );
+        assert_eq!(st.parse_uci("e7e8"), Err(UciError::Illegal));
+        assert_eq!(st.parse_uci("e1e3"), Err(UciError::Illegal));
+        assert_eq!(st.parse_uci("e7e8k"), Err(UciError::Invalid));
+        assert_eq!(st.parse_uci("e7"), Err(UciError::Invalid));
+    }
+
+    #[test]
+    fn packed_move_test() {
+        assert_eq!(std::mem::size_of::<PackedMove>(), 2);
+
+        let positions: Vec<Position> = load("test-data/positions.json");
+        for position in positions {
+            let st = State::from_fen(&position.fen);
+            for mv in legal_moves(&st) {
+                assert_eq!(Move::from(st.pack(mv)), mv, "FEN: {}", position.fen);
+            }
+        }
+
+        let flag = |st: &State, uci: &str| st.pack(st.parse_uci(uci).unwrap()).flag();
+        let mut st =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(flag(&st, "e1g1"), MoveFlag::KingCastle);
+        assert_eq!(flag(&st, "e1c1"), MoveFlag::QueenCastle);
+        assert_eq!(flag(&st, "d5e6"), MoveFlag::Capture);
+        assert_eq!(flag(&st, "a2a4"), MoveFlag::DoublePush);
+        assert_eq!(flag(&st, "a2a3"), MoveFlag::Quiet);
+        st.push(st.parse_uci("a2a4").unwrap());
+        assert_eq!(flag(&st, "b4a3"), MoveFlag::EnPassant);
+        // a diagonal pawn move to an empty square is only en passant on the ep square
+        let st = State::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        let mv = Move {
+            from: Vector::new(4, 1),
+            to: Vector::new(3, 2),
+            promotion: None,
+        };
+        assert_eq!(st.pack(mv).flag(), MoveFlag::Quiet);
+
+        let st = State::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1");
+        assert_eq!(flag(&st, "g2g1q"), MoveFlag::QueenPromotion);
+        assert_eq!(flag(&st, "g2h1n"), MoveFlag::KnightPromotionCapture);
+        let packed = st.pack(st.parse_uci("g2f1r").unwrap());
+        assert!(packed.is_capture() && packed.is_promotion());
+        assert_eq!(packed.promotion(), Some(Soldier::Rook));
+    }
+
+    // every candidate move of our pieces, with each promotion piece on the last ranks
+    fn assert_is_legal(st: &State) {
+        let legal = legal_moves(st);
+        let fen = st.to_fen();
+        for from in st.board.color_occupancy(st.turn).iter() {
+            for to in Vector::board_pos_iter() {
+                for promotion in [
+                    None,
+                    Some(Soldier::Queen),
+                    Some(Soldier::Knight),
+                    Some(Soldier::King),
+                ] {
+                    let mv = Move {
+                        from,
+                        to,
+                        promotion,
+                    };
+                    assert_eq!(st.is_legal(mv), legal.contains(&mv), "{:?} {}", mv, fen);
+                    if st.is_legal(mv) {
+                        assert!(st.is_pseudo_legal(mv));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn is_legal_test() {
+        let positions: Vec<Position> = load("test-data/positions.json");
+        for position in positions {
+            assert_is_legal(&State::from_fen(&position.fen));
+        }
+        let mut st =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        for mv in legal_moves(&st) {
+            st.push(mv);
+            assert_is_legal(&st);
+            st.pop();
+        }
+
+        // pinned pieces and castling through check follow the piece's rules,
+        // but are not legal
+        let st = State::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert_eq!(st.parse_uci("e2c3"), Err(UciError::Illegal));
+        let mv = Move {
+            from: Vector::new(4, 1),
+            to: Vector::new(2, 2),
+            promotion: None,
+        };
+        assert!(st.is_pseudo_legal(mv) && !st.is_legal(mv));
+        let st = State::from_fen("4k3/8/8/8/8/8/8/R3KNrR w KQ - 0 1");
+        let castle = |to: i8| Move {
+            from: Vector::new(4, 0),
+            to: Vector::new(to, 0),
+            promotion: None,
+        };
+        assert!(!st.is_pseudo_legal(castle(6)));
+        assert!(st.is_pseudo_legal(castle(2)) && st.is_legal(castle(2)));
+        let st = State::from_fen("4k3/8/8/8/8/8/6r1/R3K2R w KQ - 0 1");
+        assert!(st.is_pseudo_legal(castle(6)) && !st.is_legal(castle(6)));
+
+        // a move from off the board is never legal
+        let off_board = Move {
+            from: Vector::new(8, 0),
+            to: Vector::new(4, 0),
+            promotion: None,
+        };
+        assert!(!st.is_pseudo_legal(off_board));
+    }
+
+    #[test]
+    fn attacks_test() {
+        let square = |name: &str| name.parse::<Vector>().unwrap();
+        let st = State::new();
+        let f3 = st
+            .board
+            .attackers_to(square("f3"), Color::White, st.board.occupancy());
+        let names: Vec<String> = f3.iter().map(|pos| pos.to_string()).collect();
+        assert_eq!(names, ["g1", "e2", "g2"]);
+        assert!(!st.board.is_attacked(square("d4"), Color::White));
+        assert!(st.board.is_attacked(square("d6"), Color::Black));
+
+        // removing the front rook from the occupancy reveals the one behind it
+        let st = State::from_fen("4k3/8/8/3p4/8/8/3R4/3R2K1 w - - 0 1");
+        let mut occupied = st.board.occupancy();
+        let d5 = st.board.attackers_to(square("d5"), Color::White, occupied);
+        assert_eq!(d5.iter().collect::<Vec<_>>(), [square("d2")]);
+        occupied.unset(square("d2"));
+        let d5 = st.board.attackers_to(square("d5"), Color::White, occupied);
+        assert_eq!(d5.iter().collect::<Vec<_>>(), [square("d1")]);
+
+        // the map, counts and per-square queries agree everywhere
+        let positions: Vec<Position> = load("test-data/positions.json");
+        for position in positions {
+            let bd = State::from_fen(&position.fen).board;
+            for color in [Color::White, Color::Black] {
+                let map = bd.attack_map(color);
+                let counts = bd.attacker_counts(color);
+                for pos in Vector::board_pos_iter() {
+                    let attackers = bd.attackers_to(pos, color, bd.occupancy());
+                    assert_eq!(attackers.num_set(), counts[pos.as_num()] as u32);
+                    assert_eq!(map.get(pos), !attackers.is_empty());
+                    assert_eq!(bd.is_attacked(pos, color), map.get(pos));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn allocate_time_test() {
+        let ms = Duration::from_millis;
+        assert_eq!(allocate_time(ms(30_000), ms(0), None), ms(1000));
+        assert_eq!(allocate_time(ms(10_000), ms(1000), Some(10)), ms(1750));
+        // never more than the clock minus the safety margin
+        assert_eq!(allocate_time(ms(100), ms(1000), Some(1)), ms(50));
+        // huge or zero moves to go must not divide by zero
+        assert_eq!(allocate_time(ms(1000), ms(0), Some(4294967296)), ms(1));
+        assert_eq!(allocate_time(ms(1000), ms(0), Some(0)), ms(950));
+    }
+
+    #[test]
+    fn search_test() {
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let stop = AtomicBool::new(false);
+        let cases = [
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", Some("a1a8"), Some(1)),
+            ("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", Some("d2d5"), None),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", None, None),
+        ];
+        for (fen, expected, mate_in) in cases {
+            let mut st = State::from_fen(fen);
+            let mut last_mate_in = None;
+            let best = search(&mut st, &limits, &stop, |info| {
+                last_mate_in = info.mate_in()
+            });
+            assert_eq!(
+                best.map(|mv| mv.to_uci()).as_deref(),
+                expected,
+                "FEN: {}",
+                fen
+            );
+            assert_eq!(last_mate_in, mate_in, "FEN: {}", fen);
+            assert_eq!(st.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn divide_test() {
+        let mut st = State::new();
+        let divided = divide(&mut st, 3);
+        assert_eq!(divided.len(), 20);
+        assert_eq!(divided.iter().map(|(_, n)| n).sum::<u64>(), 8902);
+        let (_, e2e4) = divided
+            .iter()
+            .find(|(mv, _)| mv.to_uci() == "e2e4")
+            .unwrap();
+        assert_eq!(*e2e4, 600);
+    }
+
+    #[test]
+    fn magic_attacks_test() {
+        let walk = |pos: Vector, dirs: Vec<Vector>, occupied: &BitBoard| {
+            let mut attacks = BitBoard::new_empty();
+            for dir in dirs {
+                let mut curr = pos + dir;
+                while curr.in_bounds() {
+                    attacks.set(curr);
+                    if occupied.get(curr) {
+                        break;
+                    }
+                    curr = curr + dir;
+                }
+            }
+            attacks.0
+        };
+
+        let mut seed: u64 = 1;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            seed
+        };
+        for _ in 0..200 {
+            // roughly a quarter of the squares occupied
+            let occupied = BitBoard(next() & next());
+            for pos in Vector::board_pos_iter() {
+                let rook = walk(pos, Vector::rook_dirs(), &occupied);
+                let bishop = walk(pos, Vector::bishop_dirs(), &occupied);
+                assert_eq!(rook_attacks(pos, occupied).0, rook);
+                assert_eq!(bishop_attacks(pos, occupied).0, bishop);
+                assert_eq!(queen_attacks(pos, occupied).0, rook | bishop);
+            }
+        }
+    }
+
+    #[test]
+    fn step_attacks_test() {
+        let square = |name: &str| name.parse::<Vector>().unwrap();
+        let squares = |bb: BitBoard| bb.iter().map(|pos| pos.to_string()).collect::<Vec<_>>();
+        assert_eq!(squares(knight_attacks(square("a1"))), ["c2", "b3"]);
+        assert_eq!(knight_attacks(square("e4")).num_set(), 8);
+        assert_eq!(squares(king_attacks(square("h8"))), ["g7", "h7", "g8"]);
+        assert_eq!(king_attacks(square("e4")).num_set(), 8);
+        assert_eq!(squares(pawn_attacks(square("a2"), Color::White)), ["b3"]);
+        assert_eq!(
+            squares(pawn_attacks(square("e7"), Color::Black)),
+            ["d6", "f6"]
+        );
+    }
+
+    #[test]
+    fn fen_errors_test() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::MissingField(FenField::Side),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+                FenError::InvalidChar {
+                    field: FenField::Placement,
+                    index: 42,
+                    found: 'X',
+                },
+            ),
+            (
+                "rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankOverflow { rank: 7, index: 17 },
+            ),
+            (
+                "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankUnderflow { rank: 7, index: 16 },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR/8 w KQkq - 0 1",
+                FenError::TooManyRanks { index: 43 },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::TooFewRanks { found: 7 },
+            ),
+            (
+                "rnbqqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::InvalidKingCount {
+                    color: Color::Black,
+                    found: 0,
+                },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+                FenError::InvalidChar {
+                    field: FenField::Side,
+                    index: 0,
+                    found: 'x',
+                },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+                FenError::InvalidChar {
+                    field: FenField::Castling,
+                    index: 3,
+                    found: 'x',
+                },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1",
+                FenError::InvalidChar {
+                    field: FenField::EnPassant,
+                    index: 1,
+                    found: '4',
+                },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 z",
+                FenError::InvalidChar {
+                    field: FenField::FullmoveNumber,
+                    index: 0,
+                    found: 'z',
+                },
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0",
+                FenError::MissingField(FenField::FullmoveNumber),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKKNR w - - 0 1",
+                FenError::InvalidKingCount {
+                    color: Color::White,
+                    found: 2,
+                },
+            ),
+            (
+                "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::PawnOnBackRank(Vector::new(0, 7)),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/p3K3 b - - 0 1",
+                FenError::PawnOnBackRank(Vector::new(0, 0)),
+            ),
+            (
+                "QQQQ1Qbk/Q4Qpp/Q5QQ/4Q3/Q6Q/Q6Q/Q6Q/KQQQQQQQ w - - 0 1",
+                FenError::TooMuchMaterial(Color::White),
+            ),
+            (
+                "4k3/pppppppp/p7/8/8/8/8/4K3 w - - 0 1",
+                FenError::TooMuchMaterial(Color::Black),
+            ),
+            (
+                "4k3/8/8/8/8/8/PPPPPPP1/QQQ1K3 w - - 0 1",
+                FenError::TooMuchMaterial(Color::White),
+            ),
+            (
+                "4k3/8/8/8/8/8/PPPPPPPP/NNN1K3 w - - 0 1",
+                FenError::TooMuchMaterial(Color::White),
+            ),
+            (
+                "8/8/8/8/8/8/8/K6k w Q - 0 1",
+                FenError::InvalidCastlingRight('Q'),
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K1R1 w Kq - 0 1",
+                FenError::InvalidCastlingRight('K'),
+            ),
+            ("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", FenError::OpponentInCheck),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 1",
+                FenError::InvalidEnPassantSquare(Vector::new(4, 2)),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1",
+                FenError::InvalidEnPassantSquare(Vector::new(4, 2)),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+                FenError::FullmoveNumberZero,
+            ),
+        ];
+
+        for (fen, expected) in cases {
+            assert_eq!(
+                State::try_from_fen(fen).err(),
+                Some(expected),
+                "FEN: {}",
+                fen
+            );
+        }
+
+        // promoted pieces are fine while there are missing pawns to account for them
+        for fen in [
+            "4k3/8/8/8/8/8/PPPPPPP1/QQ2K3 w - - 0 1",
+            "R6R/3Q4/1Q4Q1/4Q3/2Q4Q/Q4Q2/pp1Q4/kBNN1KB1 w - - 0 1",
+        ] {
+            assert!(State::try_from_fen(fen).is_ok(), "FEN: {}", fen);
         }
     }
 }
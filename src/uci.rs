@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+use crate::{board::Soldier, moves::Move, state::State, vector::Vector};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UciError {
+    // the text is not a move
+    Invalid,
+    // the move is well formed but not legal in the position
+    Illegal,
+}
+
+impl Display for UciError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UciError::Invalid => write!(f, "invalid UCI move"),
+            UciError::Illegal => write!(f, "illegal move"),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+impl Move {
+    // long algebraic notation as used by UCI, e.g. e2e4 or e7e8q
+    pub fn to_uci(&self) -> String {
+        match self.promotion {
+            Some(s) => format!(
+                "{}{}{}",
+                self.from,
+                self.to,
+                s.letter().to_ascii_lowercase()
+            ),
+            None => format!("{}{}", self.from, self.to),
+        }
+    }
+}
+
+impl State {
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, UciError> {
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(UciError::Invalid);
+        }
+        let from: Vector = uci[0..2].parse().map_err(|_| UciError::Invalid)?;
+        let to: Vector = uci[2..4].parse().map_err(|_| UciError::Invalid)?;
+        let promotion = match &uci[4..] {
+            "" => None,
+            "n" => Some(Soldier::Knight),
+            "b" => Some(Soldier::Bishop),
+            "r" => Some(Soldier::Rook),
+            "q" => Some(Soldier::Queen),
+            _ => return Err(UciError::Invalid),
+        };
+
+        let mv = Move {
+            from,
+            to,
+            promotion,
+        };
+        if self.is_legal(mv) {
+            Ok(mv)
+        } else {
+            Err(UciError::Illegal)
+        }
+    }
+}